@@ -1,6 +1,7 @@
 import QtQuick 2.8
 import QtQuick.Layouts 1.3
 import QtQuick.Controls 2.3
+import QtQuick.Dialogs 1.3
 
 Rectangle {
     color: Qt.rgba(0, 0, 0, 0.3)
@@ -17,6 +18,10 @@ Rectangle {
         return parseFloat((bytes / Math.pow(k, i)).toFixed(dm)) + ' ' + sizes[i];
     }
 
+    function urlToPath(url) {
+        return decodeURIComponent(url.toString().replace(/^file:\/\//, ""))
+    }
+
     Pane {
         height: parent.height
         width: 600
@@ -80,13 +85,6 @@ Rectangle {
                     Layout.fillWidth: true
                     title: qsTr("Automatically Change Wallpaper")
 
-                    Timer {
-                        running: wallpapers.config.auto_change.enable
-                        repeat: true
-                        interval: wallpapers.config.auto_change.interval * 60 * 1000
-                        onTriggered: wallpapers.next_wallpaper()
-                    }
-
                     GridLayout {
                         width: parent.width
                         rowSpacing: 10
@@ -98,8 +96,9 @@ Rectangle {
                         Switch {
                             id: autoChangeWallpaperBtn
                             Layout.alignment: Qt.AlignRight
-                            Component.onCompleted: checked = wallpapers.config.auto_change.enable
-                            onCheckedChanged: wallpapers.config.auto_change.enable = checked
+                            // The tray can change it too
+                            checked: wallpapers.config.auto_change.enable
+                            onToggled: wallpapers.config.auto_change.enable = checked
                         }
 
                         Label {
@@ -121,6 +120,16 @@ Rectangle {
                             }
                         }
 
+                        Label {
+                            text: qsTr("A different wallpaper on each monitor (if the desktop supports it)")
+                        }
+                        Switch {
+                            Layout.alignment: Qt.AlignRight
+                            enabled: autoChangeWallpaperBtn.checked
+                            checked: wallpapers.config.auto_change.per_monitor
+                            onToggled: wallpapers.config.auto_change.per_monitor = checked
+                        }
+
                         GroupBox {
                             Layout.columnSpan: 2
                             Layout.fillWidth: true
@@ -131,20 +140,20 @@ Rectangle {
                                 enabled: autoChangeWallpaperBtn.checked
                                 RadioButton {
                                     Layout.alignment: Qt.AlignHCenter
-                                    Component.onCompleted: checked = wallpapers.config.auto_change.mode == 0
-                                    onCheckedChanged: wallpapers.config.auto_change.mode = 0
+                                    checked: wallpapers.config.auto_change.mode == 0
+                                    onToggled: wallpapers.config.auto_change.mode = 0
                                     text: qsTr("Newest")
                                 }
                                 RadioButton {
                                     Layout.alignment: Qt.AlignHCenter
-                                    Component.onCompleted: checked = wallpapers.config.auto_change.mode == 1
-                                    onCheckedChanged: wallpapers.config.auto_change.mode = 1
+                                    checked: wallpapers.config.auto_change.mode == 1
+                                    onToggled: wallpapers.config.auto_change.mode = 1
                                     text: qsTr("Favourites")
                                 }
                                 RadioButton {
                                     Layout.alignment: Qt.AlignHCenter
-                                    Component.onCompleted: checked = wallpapers.config.auto_change.mode == 2
-                                    onCheckedChanged: wallpapers.config.auto_change.mode = 2
+                                    checked: wallpapers.config.auto_change.mode == 2
+                                    onToggled: wallpapers.config.auto_change.mode = 2
                                     // TODO: rename to downloaded
                                     text: qsTr("Random")
                                 }
@@ -153,6 +162,94 @@ Rectangle {
                     }
                 }
 
+                GroupBox {
+                    title: qsTr("Source")
+                    Layout.fillWidth: true
+
+                    ColumnLayout {
+                        width: parent.width
+
+                        ComboBox {
+                            Layout.fillWidth: true
+                            // In the order of `SourceKind::ALL`
+                            model: [qsTr("bohan's mirror"), qsTr("Bing (last two weeks only)"), qsTr("Local folder")]
+                            currentIndex: 0
+                            Component.onCompleted: currentIndex = wallpapers.config.source_index
+                            onActivated: {
+                                wallpapers.config.source_index = index
+                                wallpapers.reload()
+                            }
+                        }
+
+                        Label {
+                            text: qsTr("Local folder, its images can be favourited with any source:")
+                        }
+
+                        TextField {
+                            Layout.fillWidth: true
+                            selectByMouse: true
+                            placeholderText: "~/Pictures/Wallpapers"
+                            Component.onCompleted: text = wallpapers.config.local_dir
+                            onEditingFinished: if (text != wallpapers.config.local_dir) {
+                                wallpapers.config.local_dir = text
+                                wallpapers.reload()
+                            }
+                        }
+                    }
+                }
+
+                GroupBox {
+                    title: qsTr("Network")
+                    Layout.fillWidth: true
+
+                    GridLayout {
+                        width: parent.width
+                        columns: 2
+
+                        Label {
+                            text: qsTr("Proxy")
+                        }
+                        TextField {
+                            Layout.fillWidth: true
+                            selectByMouse: true
+                            placeholderText: "socks5://127.0.0.1:1080"
+                            Component.onCompleted: text = wallpapers.config.network.proxy
+                            onEditingFinished: if (text != wallpapers.config.network.proxy) {
+                                wallpapers.config.network.proxy = text
+                                wallpapers.reload()
+                            }
+                        }
+
+                        Label {
+                            text: qsTr("Mirror API")
+                        }
+                        TextField {
+                            Layout.fillWidth: true
+                            selectByMouse: true
+                            placeholderText: "https://leanapi.bohan.co"
+                            Component.onCompleted: text = wallpapers.config.network.api_base
+                            onEditingFinished: if (text != wallpapers.config.network.api_base) {
+                                wallpapers.config.network.api_base = text
+                                wallpapers.reload()
+                            }
+                        }
+
+                        Label {
+                            text: qsTr("Mirror images")
+                        }
+                        TextField {
+                            Layout.fillWidth: true
+                            selectByMouse: true
+                            placeholderText: "https://wpdn.bohan.co"
+                            Component.onCompleted: text = wallpapers.config.network.cdn_base
+                            onEditingFinished: if (text != wallpapers.config.network.cdn_base) {
+                                wallpapers.config.network.cdn_base = text
+                                wallpapers.reload()
+                            }
+                        }
+                    }
+                }
+
                 GroupBox {
                     title: qsTr("Resolution")
                     Layout.fillWidth: true
@@ -178,6 +275,7 @@ Rectangle {
                         ComboBox {
                             id: downloadResolution
                             Layout.alignment: Qt.AlignRight
+                            enabled: !autoResolution.checked
                             currentIndex: 0
                             Component.onCompleted: currentIndex = wallpapers.config.resolution.download_index
                             onCurrentIndexChanged: wallpapers.config.resolution.download_index = currentIndex
@@ -195,6 +293,70 @@ Rectangle {
                             Component.onCompleted: checked = wallpapers.config.resolution.original
                             onCheckedChanged: wallpapers.config.resolution.original = checked
                         }
+
+                        Label {
+                            text: qsTr("Detect from screens")
+                        }
+                        Switch {
+                            id: autoResolution
+                            Component.onCompleted: checked = wallpapers.config.resolution.auto_detect
+                            onCheckedChanged: wallpapers.config.resolution.auto_detect = checked
+                        }
+                        Label {
+                            Layout.columnSpan: 2
+                            Layout.alignment: Qt.AlignRight
+                            visible: autoResolution.checked && wallpapers.config.screen_resolution
+                            text: qsTr("Using %1").arg(wallpapers.config.screen_resolution)
+                        }
+
+                        Label {
+                            text: qsTr("Fit to screens")
+                        }
+                        ComboBox {
+                            Layout.columnSpan: 3
+                            Layout.alignment: Qt.AlignRight
+                            model: [qsTr("Off"), qsTr("Fill"), qsTr("Fit"), qsTr("Smart crop")]
+                            Component.onCompleted: currentIndex = wallpapers.config.resolution.scale
+                            onCurrentIndexChanged: wallpapers.config.resolution.scale = currentIndex
+                        }
+                    }
+                }
+
+                GroupBox {
+                    title: qsTr("Favourites")
+                    Layout.fillWidth: true
+
+                    RowLayout {
+                        width: parent.width
+
+                        CheckBox {
+                            id: exportImages
+                            Layout.fillWidth: true
+                            text: qsTr("Export with images")
+                        }
+                        Button {
+                            text: qsTr("Export")
+                            onClicked: exportDialog.open()
+                        }
+                        Button {
+                            text: qsTr("Import")
+                            onClicked: importDialog.open()
+                        }
+                    }
+
+                    FileDialog {
+                        id: exportDialog
+                        title: qsTr("Export favourites")
+                        selectExisting: false
+                        nameFilters: exportImages.checked ? ["Tar archive (*.tar)"] : ["JSON (*.json)"]
+                        onAccepted: wallpapers.export_favorites(urlToPath(fileUrl), exportImages.checked)
+                    }
+
+                    FileDialog {
+                        id: importDialog
+                        title: qsTr("Import favourites")
+                        nameFilters: ["Favourites (*.json *.tar)"]
+                        onAccepted: wallpapers.import_favorites(urlToPath(fileUrl))
                     }
                 }
 
@@ -16,6 +16,7 @@ GridView {
     property bool loading
     signal nextPage()
     signal download(int index)
+    signal cancelDownload(int index)
     signal likeClicked(int index)
     signal setWallpaperClicked(int index)
 
@@ -90,10 +91,24 @@ GridView {
                 radius: 128
             }
 
-            BusyIndicator {
-                height: 60
+            ColumnLayout {
                 visible: model.loading
                 anchors.centerIn: parent
+                z: 1
+
+                BusyIndicator {
+                    Layout.preferredHeight: 60
+                    Layout.alignment: Qt.AlignHCenter
+                }
+                ProgressBar {
+                    Layout.alignment: Qt.AlignHCenter
+                    value: model.progress
+                }
+                Button {
+                    Layout.alignment: Qt.AlignHCenter
+                    text: qsTr("Cancel")
+                    onClicked: cancelDownload(index)
+                }
             }
 
             Image {
@@ -1,4 +1,5 @@
 import QtQuick 2.8
+import QtQml 2.2
 import QtQuick.Layouts 1.3
 import QtQuick.Controls 2.3
 import QtGraphicalEffects 1.0
@@ -27,12 +28,56 @@ ApplicationWindow {
         radius: 64
     }
 
+    // Physical sizes of the screens, the download resolution is chosen for them
+    function reportScreens() {
+        var sizes = []
+        for (var i = 0; i < Qt.application.screens.length; i++) {
+            var screen = Qt.application.screens[i]
+            sizes.push(Math.round(screen.width * screen.devicePixelRatio) + "x"
+                       + Math.round(screen.height * screen.devicePixelRatio))
+        }
+        wallpapers.config.set_screens(sizes.join(","))
+    }
+
+    // Follow screens plugged, unplugged, rotated or scaled
+    Instantiator {
+        model: Qt.application.screens
+        delegate: Connections {
+            target: modelData
+            onWidthChanged: window.reportScreens()
+            onHeightChanged: window.reportScreens()
+            onDevicePixelRatioChanged: window.reportScreens()
+        }
+        onObjectAdded: window.reportScreens()
+        onObjectRemoved: window.reportScreens()
+    }
+
     Component.onCompleted: {
-        wallpapers.onError.connect(function(err) {
-            console.log("error:", err)
+        reportScreens()
+        wallpapers.onError.connect(function(err, category, retry) {
+            console.log("error:", category, err)
+            errorText.text = retry ? qsTr("%1 (try again later)").arg(err) : err
+            errorPopup.open()
         })
     }
 
+    Popup {
+        id: errorPopup
+        x: (window.width - width) / 2
+        y: window.height - height - 20
+        z: 1
+
+        Label {
+            id: errorText
+        }
+
+        Timer {
+            running: errorPopup.visible
+            interval: 5000
+            onTriggered: errorPopup.close()
+        }
+    }
+
     TrayProxy {
         onOpen: {
             window.show()
@@ -42,6 +87,21 @@ ApplicationWindow {
         onQuit: {
             Qt.quit()
         }
+        onToggle_like: {
+            wallpapers.like_current()
+        }
+        onPause: {
+            wallpapers.config.auto_change.enable = !paused
+        }
+        onSet_mode: {
+            wallpapers.config.auto_change.mode = mode
+        }
+        onImport_favorites: {
+            wallpapers.import_favorites(path)
+        }
+        onSet_config: {
+            wallpapers.set_config(key, value)
+        }
         Component.onCompleted: {
             connect_to_backend()
         }
@@ -79,6 +139,9 @@ ApplicationWindow {
                 onDownload: {
                     wallpapers.download(index, true)
                 }
+                onCancelDownload: {
+                    wallpapers.cancel_download(index, true)
+                }
                 onLikeClicked: {
                     wallpapers.like(index, true)
                 }
@@ -99,6 +162,9 @@ ApplicationWindow {
                 onDownload: {
                     wallpapers.download(index, false)
                 }
+                onCancelDownload: {
+                    wallpapers.cancel_download(index, false)
+                }
                 onLikeClicked: {
                     wallpapers.like(index, false)
                 }
@@ -28,3 +28,11 @@ pub async fn enter_tokio<T>(mut f: impl Future<Output = T>) -> T {
     })
     .await
 }
+
+/// Run a future on the background tokio runtime, independent of any Qt event loop
+pub fn spawn<F>(f: F)
+where
+    F: Future<Output = ()> + Send + 'static,
+{
+    HANDLE.spawn(f);
+}
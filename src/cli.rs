@@ -0,0 +1,164 @@
+use std::env;
+use std::path::Path;
+
+use failure::format_err;
+use futures::executor::block_on;
+
+use crate::async_utils::enter_tokio;
+use crate::config::Config;
+use crate::favorites::Favorite;
+use crate::implementation;
+use crate::instance;
+use crate::source;
+use crate::systray::Cmd;
+
+const USAGE: &str = "\
+Usage: biying [--daemon]
+       biying next
+       biying quit
+       biying like-current
+       biying set <id>
+       biying list [--favorites]
+       biying favorites export <file> [--images]
+       biying favorites import <file>
+       biying config get <key>
+       biying config set <key> <value>";
+
+/// Run the command in `args`, without starting Qt.
+/// Returns `None` if there is no command, then the GUI should be started
+pub fn run(args: &[String]) -> Option<Result<(), failure::Error>> {
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+    let r = match &*args {
+        [] | ["--daemon"] => return None,
+        ["help"] | ["--help"] | ["-h"] => {
+            println!("{}", USAGE);
+            Ok(())
+        }
+        ["next"] => next(),
+        ["quit"] => quit(),
+        ["like-current"] => like_current(),
+        ["set", id] => set(id),
+        ["list"] => list(false),
+        ["list", "--favorites"] => list(true),
+        ["favorites", "export", file] => export_favorites(file, false),
+        ["favorites", "export", file, "--images"] => export_favorites(file, true),
+        ["favorites", "import", file] => import_favorites(file),
+        ["config", "get", key] => config_get(key),
+        ["config", "set", key, value] => config_set(key, value),
+        _ => Err(format_err!(
+            "Unknown command: {}\n{}",
+            args.join(" "),
+            USAGE
+        )),
+    };
+    Some(r)
+}
+
+fn open_config() -> Config {
+    let config = Config::open().unwrap_or_default();
+    crate::prepare_config(&config);
+    source::configure(&config.snapshot());
+    config
+}
+
+fn next() -> Result<(), failure::Error> {
+    if instance::forward(Cmd::Next) {
+        return Ok(());
+    }
+    let config = open_config().snapshot();
+    block_on(enter_tokio(implementation::next_wallpaper(&config)))
+}
+
+fn quit() -> Result<(), failure::Error> {
+    if !instance::forward(Cmd::Quit) {
+        return Err(format_err!("{} is not running", env!("CARGO_PKG_NAME")));
+    }
+    Ok(())
+}
+
+fn like_current() -> Result<(), failure::Error> {
+    if instance::forward(Cmd::ToggleLike) {
+        return Ok(());
+    }
+    let (id, liked) = implementation::toggle_like_current(&mut open_config())?;
+    println!("{} {}", if liked { "Liked" } else { "Unliked" }, id);
+    Ok(())
+}
+
+fn set(id: &str) -> Result<(), failure::Error> {
+    if instance::forward(Cmd::SetById(id.to_owned())) {
+        return Ok(());
+    }
+    let config = open_config().snapshot();
+    block_on(enter_tokio(implementation::set_wallpaper_by_id(
+        &config, id,
+    )))
+}
+
+fn list(favorites: bool) -> Result<(), failure::Error> {
+    let config = open_config();
+    let images = if favorites {
+        config.favorites.iter().map(Favorite::to_raw).collect()
+    } else {
+        block_on(enter_tokio(source::list(config.source(), 0, 20)))?
+    };
+    for img in images {
+        println!("{}\t{}\t{}", img.object_id, img.name, img.copyright);
+    }
+    Ok(())
+}
+
+fn export_favorites(file: &str, with_images: bool) -> Result<(), failure::Error> {
+    let config = open_config();
+    let download_dir = if with_images {
+        Some(&*config.download_dir)
+    } else {
+        None
+    };
+    config.favorites.export(Path::new(file), download_dir)
+}
+
+fn import_favorites(file: &str) -> Result<(), failure::Error> {
+    // The running instance may be in another directory
+    let path = env::current_dir()?.join(file);
+    let path = path
+        .to_str()
+        .ok_or_else(|| format_err!("Invalid path: {}", file))?;
+    if instance::forward(Cmd::ImportFavorites(path.to_owned())) {
+        return Ok(());
+    }
+    let mut config = open_config();
+    let count = config
+        .favorites
+        .import(Path::new(path), &config.download_dir)?;
+    config.save_favorites()?;
+    println!("Imported {} new favorites", count);
+    Ok(())
+}
+
+fn config_get(key: &str) -> Result<(), failure::Error> {
+    let config = toml::Value::try_from(open_config())?;
+    let value = lookup(&config, key)?;
+    match value {
+        toml::Value::String(s) => println!("{}", s),
+        v => println!("{}", v),
+    }
+    Ok(())
+}
+
+fn config_set(key: &str, value: &str) -> Result<(), failure::Error> {
+    // Checked here, so mistakes are reported to the user
+    let config = open_config().with_value(key, value)?;
+    // The running instance would overwrite the file with its own config
+    if instance::forward(Cmd::SetConfig(key.to_owned(), value.to_owned())) {
+        return Ok(());
+    }
+    config.save()?;
+    Ok(())
+}
+
+fn lookup<'a>(value: &'a toml::Value, key: &str) -> Result<&'a toml::Value, failure::Error> {
+    key.split('.')
+        .try_fold(value, |v, k| v.get(k))
+        .ok_or_else(|| format_err!("Unknown config key: {}", key))
+}
@@ -4,12 +4,19 @@ use std::fs;
 use std::io::prelude::*;
 use std::iter::FromIterator;
 use std::path::PathBuf;
+use std::sync::Arc;
 
+use failure::{self, format_err};
 use qmetaobject::*;
 use serde::{Deserialize, Serialize};
 use toml;
 
+use crate::favorites::Favorites;
 use crate::listmodel::{MutListItem, MutListModel};
+use crate::monitor;
+use crate::scheduler;
+use crate::setter::{Argv, FirstInstalled, GSettings, Plasma, Shell, WallpaperSetter, Xfconf};
+use crate::source::SourceKind;
 
 #[derive(QObject, Serialize, Deserialize)]
 pub struct Config {
@@ -17,10 +24,22 @@ pub struct Config {
     base: qt_base_class!(trait QObject),
     #[serde(rename = "custom_cmd", with = "custom_cmd")]
     pub de: qt_property!(RefCell<MutListModel<DesktopEnviroment>>; CONST),
-    pub de_index: qt_property!(usize; NOTIFY s1),
-    pub auto_change: qt_property!(AutoChangeConfig; NOTIFY s2),
-    pub resolution: qt_property!(Resolution; NOTIFY s3),
+    #[serde(with = "de_name")]
+    pub de_index: qt_property!(usize; WRITE set_de_index NOTIFY s1),
+    pub auto_change: qt_property!(AutoChangeConfig; WRITE set_auto_change NOTIFY s2),
+    pub resolution: qt_property!(Resolution; WRITE set_resolution NOTIFY s3),
     pub autoremove: qt_property!(u64; NOTIFY s4),
+    #[serde(rename = "source", with = "source_kind", default)]
+    pub source_index: qt_property!(usize; WRITE set_source_index NOTIFY s5),
+    #[serde(with = "qstring", default)]
+    pub local_dir: qt_property!(QString; WRITE set_local_dir NOTIFY s6),
+    #[serde(default)]
+    pub network: qt_property!(NetworkConfig; WRITE set_network NOTIFY s7),
+    /// Chosen for the screens when `resolution.auto_detect` is on
+    #[serde(skip)]
+    pub screen_resolution: qt_property!(QString; NOTIFY s8),
+    #[serde(skip)]
+    pub set_screens: qt_method!(fn(&mut self, sizes: QString)),
     #[serde(skip)]
     s1: qt_signal!(),
     #[serde(skip)]
@@ -29,9 +48,27 @@ pub struct Config {
     s3: qt_signal!(),
     #[serde(skip)]
     s4: qt_signal!(),
+    #[serde(skip)]
+    s5: qt_signal!(),
+    #[serde(skip)]
+    s6: qt_signal!(),
+    #[serde(skip)]
+    s7: qt_signal!(),
+    #[serde(skip)]
+    s8: qt_signal!(),
     pub download_dir: PathBuf,
     pub cache_dir: PathBuf,
-    pub likes: Vec<String>,
+    /// Only read to migrate to `favorites`, kept until they are saved there
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    likes: Vec<String>,
+    #[serde(skip)]
+    pub favorites: Favorites,
+    /// `favorites.json` couldn't be read, so it's never overwritten
+    #[serde(skip)]
+    favorites_unread: bool,
+    /// In physical pixels, reported by QML
+    #[serde(skip)]
+    screens: Vec<(u32, u32)>,
 }
 
 impl Config {
@@ -39,7 +76,29 @@ impl Config {
         let mut f = fs::File::open(Self::config_dir().join("config.toml"))?;
         let mut s = String::new();
         f.read_to_string(&mut s)?;
-        let config = toml::from_str(&s)?;
+        let mut config: Config = toml::from_str(&s)?;
+        // The config is still good without favorites
+        match Favorites::open(&Self::config_dir(), config.likes.clone()) {
+            Ok(favorites) => config.favorites = favorites,
+            Err(e) => {
+                eprintln!("Failed to open favorites: {}", e);
+                config.favorites_unread = true;
+            }
+        }
+        if !config.likes.is_empty() && !config.favorites_unread {
+            // Likes leave config.toml only after they are in favorites.json,
+            // or they are migrated again on next start
+            let r = config.save_favorites().and_then(|()| {
+                let likes = std::mem::take(&mut config.likes);
+                config.save().map_err(|e| {
+                    config.likes = likes;
+                    e
+                })
+            });
+            if let Err(e) = r {
+                eprintln!("Failed to migrate likes: {}", e);
+            }
+        }
         Ok(config)
     }
 
@@ -49,12 +108,241 @@ impl Config {
             fs::create_dir_all(&path)?;
         }
         let mut f = fs::File::create(path.join("config.toml"))?;
-        let s = toml::Value::try_from(self).unwrap();
+        let s = toml::Value::try_from(self)?;
         let mut v = toml::to_vec(&s)?;
         f.write_all(&mut v)?;
+        self.save_favorites()
+    }
+
+    pub fn save_favorites(&self) -> Result<(), failure::Error> {
+        if self.favorites_unread {
+            return Err(format_err!("Favorites weren't loaded, not saving them"));
+        }
+        self.favorites.save(&Self::config_dir())
+    }
+
+    /// A copy with `key` like `auto_change.interval` set to `value`, any TOML value,
+    /// bare words are taken as strings
+    pub fn with_value(&self, key: &str, value: &str) -> Result<Config, failure::Error> {
+        let mut config = toml::Value::try_from(self)?;
+        let value = format!("v = {}", value)
+            .parse::<toml::Value>()
+            .ok()
+            .and_then(|mut v| v.as_table_mut()?.remove("v"))
+            .unwrap_or_else(|| toml::Value::String(value.to_owned()));
+        let field = key
+            .split('.')
+            .try_fold(&mut config, |v, k| v.get_mut(k))
+            .ok_or_else(|| format_err!("Unknown config key: {}", key))?;
+        *field = value;
+        let mut config: Config = config.try_into()?;
+        config.check()?;
+        config.favorites = self.favorites.clone();
+        config.favorites_unread = self.favorites_unread;
+        config.screens = self.screens.clone();
+        Ok(config)
+    }
+
+    /// Values the settings page can't make
+    fn check(&self) -> Result<(), failure::Error> {
+        let resolution = &self.resolution;
+        let auto_change = &self.auto_change;
+        if resolution.download_index >= resolution.download.len() {
+            let max = resolution.download.len() - 1;
+            return Err(format_err!(
+                "resolution.download_index must be 0 to {}",
+                max
+            ));
+        }
+        if resolution.preview_index >= resolution.preview.len() {
+            let max = resolution.preview.len() - 1;
+            return Err(format_err!("resolution.preview_index must be 0 to {}", max));
+        }
+        if resolution.scale > 3 {
+            return Err(format_err!("resolution.scale must be 0 to 3"));
+        }
+        if auto_change.interval < 1 {
+            return Err(format_err!("auto_change.interval must be at least 1"));
+        }
+        if auto_change.mode > 2 {
+            return Err(format_err!("auto_change.mode must be 0 to 2"));
+        }
         Ok(())
     }
 
+    /// Take the settings of `other`, like they are changed in settings
+    pub fn replace(&mut self, other: Config) {
+        let cmd = String::from_utf16_lossy(other.de.borrow().iter().last().unwrap().cmd.to_slice());
+        let mut de = self.de.borrow_mut();
+        let custom = de.len() - 1;
+        de.change_line(custom, DesktopEnviroment::custom(&cmd));
+        drop(de);
+        self.autoremove = other.autoremove;
+        self.s4();
+        self.download_dir = other.download_dir;
+        self.cache_dir = other.cache_dir;
+        self.set_de_index(other.de_index);
+        self.set_auto_change(other.auto_change);
+        self.set_resolution(other.resolution);
+        self.set_source_index(other.source_index);
+        self.set_local_dir(other.local_dir);
+        self.set_network(other.network);
+    }
+
+    pub fn download_resolution(&self) -> String {
+        if self.resolution.auto_detect {
+            if let Some(res) = self.detected_resolution() {
+                return res;
+            }
+        }
+        // An index from a newer or edited config may be out of the list
+        let resolutions = self.resolutions();
+        resolutions
+            .get(self.resolution.download_index)
+            .or_else(|| resolutions.first())
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    /// For the largest screen, if a wallpaper is shared by all screens it looks fine there
+    fn detected_resolution(&self) -> Option<String> {
+        let (width, height) = self
+            .screens
+            .iter()
+            .max_by_key(|(w, h)| *w as u64 * *h as u64)?;
+        monitor::best_resolution(&self.resolutions(), *width, *height)
+    }
+
+    /// Resolutions wallpapers may be downloaded in now, for every screen
+    pub fn kept_resolutions(&self) -> Vec<String> {
+        let mut kept = vec![self.download_resolution(), self.portrait_resolution()];
+        if self.resolution.auto_detect {
+            let resolutions = self.resolutions();
+            // Not reported by QML yet, the scheduler may have used any of them
+            if self.screens.is_empty() {
+                kept.extend(resolutions);
+                return kept;
+            }
+            kept.extend(
+                self.screens
+                    .iter()
+                    .filter_map(|(w, h)| monitor::best_resolution(&resolutions, *w, *h)),
+            );
+        }
+        kept
+    }
+
+    /// All resolutions can be downloaded
+    pub fn resolutions(&self) -> Vec<String> {
+        let download = &self.resolution.download;
+        (0..download.len())
+            .map(|i| download[i].to_qbytearray().to_string())
+            .collect()
+    }
+
+    /// For portrait monitors, the download resolution rotated if it's in the list,
+    /// or the first portrait one
+    pub fn portrait_resolution(&self) -> String {
+        let portrait = self.screens.iter().find(|(w, h)| h > w);
+        if let Some((width, height)) = portrait.filter(|_| self.resolution.auto_detect) {
+            if let Some(res) = monitor::best_resolution(&self.resolutions(), *width, *height) {
+                return res;
+            }
+        }
+        let resolution = self.download_resolution();
+        let size = |res: &str| -> Option<(u32, u32)> {
+            let (width, height) = res.split_once('x')?;
+            Some((width.parse().ok()?, height.parse().ok()?))
+        };
+        let portraits: Vec<String> = self
+            .resolutions()
+            .into_iter()
+            .filter(|res| size(res).map_or(false, |(w, h)| h > w))
+            .collect();
+        let rotated = size(&resolution).map(|(w, h)| format!("{}x{}", h, w));
+        match rotated {
+            Some(rotated) if portraits.contains(&rotated) => rotated,
+            _ => portraits.into_iter().next().unwrap_or(resolution),
+        }
+    }
+
+    pub fn source(&self) -> SourceKind {
+        SourceKind::ALL[self.source_index]
+    }
+
+    pub fn snapshot(&self) -> Snapshot {
+        let de = &self.de.borrow()[self.de_index];
+        Snapshot {
+            source: self.source(),
+            auto_change: self.auto_change.clone(),
+            resolution: self.download_resolution(),
+            portrait_resolution: self.portrait_resolution(),
+            auto_resolution: self.resolution.auto_detect,
+            resolutions: self.resolutions(),
+            original: self.resolution.original,
+            scale: self.resolution.scale,
+            download_dir: self.download_dir.clone(),
+            cache_dir: self.cache_dir.clone(),
+            local_dir: String::from_utf16_lossy(self.local_dir.to_slice()).into(),
+            favorites: self.favorites.clone(),
+            api_base: String::from_utf16_lossy(self.network.api_base.to_slice()),
+            cdn_base: String::from_utf16_lossy(self.network.cdn_base.to_slice()),
+            proxy: String::from_utf16_lossy(self.network.proxy.to_slice()),
+            setter: de.setter.clone(),
+        }
+    }
+
+    fn set_de_index(&mut self, v: usize) {
+        self.de_index = v;
+        self.s1();
+        scheduler::update(self);
+    }
+
+    fn set_auto_change(&mut self, v: AutoChangeConfig) {
+        self.auto_change = v;
+        self.s2();
+        scheduler::update(self);
+    }
+
+    fn set_resolution(&mut self, v: Resolution) {
+        self.resolution = v;
+        self.s3();
+        scheduler::update(self);
+    }
+
+    fn set_source_index(&mut self, v: usize) {
+        self.source_index = v;
+        self.s5();
+        scheduler::update(self);
+    }
+
+    fn set_local_dir(&mut self, v: QString) {
+        self.local_dir = v;
+        self.s6();
+        scheduler::update(self);
+    }
+
+    fn set_network(&mut self, v: NetworkConfig) {
+        self.network = v;
+        self.s7();
+        scheduler::update(self);
+    }
+
+    /// `sizes` is like `1920x1080,1080x1920`
+    fn set_screens(&mut self, sizes: QString) {
+        let sizes = String::from_utf16_lossy(sizes.to_slice());
+        self.screens = sizes
+            .split(',')
+            .filter_map(|size| {
+                let (width, height) = size.split_once('x')?;
+                Some((width.parse().ok()?, height.parse().ok()?))
+            })
+            .collect();
+        self.screen_resolution = self.detected_resolution().unwrap_or_default().into();
+        self.s8();
+        scheduler::update(self);
+    }
+
     fn config_dir() -> PathBuf {
         env::var("XDG_CONFIG_HOME")
             .map_or_else(
@@ -65,6 +353,31 @@ impl Config {
     }
 }
 
+/// The parts of `Config` needed to change wallpaper, without any QObject,
+/// so it can be moved to the tokio runtime and outlive the QML engine
+#[derive(Clone)]
+pub struct Snapshot {
+    pub source: SourceKind,
+    pub auto_change: AutoChangeConfig,
+    pub resolution: String,
+    pub portrait_resolution: String,
+    /// Detect monitors and choose from `resolutions` for them,
+    /// the ones above are used if they can't be found
+    pub auto_resolution: bool,
+    pub resolutions: Vec<String>,
+    pub original: bool,
+    /// See `Resolution::scale`
+    pub scale: u8,
+    pub download_dir: PathBuf,
+    pub cache_dir: PathBuf,
+    pub local_dir: PathBuf,
+    pub favorites: Favorites,
+    pub api_base: String,
+    pub cdn_base: String,
+    pub proxy: String,
+    pub setter: Arc<dyn WallpaperSetter>,
+}
+
 // Only read and save the `cmd` of "Other", ignore default values
 mod custom_cmd {
     use super::*;
@@ -87,10 +400,7 @@ mod custom_cmd {
     {
         let cmd = String::deserialize(de)?;
         let mut de = default_de_list();
-        de.push(DesktopEnviroment {
-            name: "Other".into(),
-            cmd: cmd.into(),
-        });
+        de.push(DesktopEnviroment::custom(&cmd));
         Ok(RefCell::new(<_>::from_iter(de)))
     }
 }
@@ -98,10 +408,12 @@ mod custom_cmd {
 impl Default for Config {
     fn default() -> Self {
         let mut de = default_de_list();
-        de.push(DesktopEnviroment {
-            name: "Other".into(),
-            cmd: "".into(),
-        });
+        de.push(DesktopEnviroment::custom(""));
+        // Keep favorites even if the config is broken
+        let favorites = Favorites::open(&Self::config_dir(), Vec::new());
+        if let Err(e) = &favorites {
+            eprintln!("Failed to open favorites: {}", e);
+        }
         Self {
             base: Default::default(),
             de: RefCell::new(<_>::from_iter(de)),
@@ -109,10 +421,19 @@ impl Default for Config {
             auto_change: Default::default(),
             resolution: Default::default(),
             autoremove: 30,
+            source_index: 0,
+            local_dir: Default::default(),
+            network: Default::default(),
+            screen_resolution: Default::default(),
+            set_screens: Default::default(),
             s1: Default::default(),
             s2: Default::default(),
             s3: Default::default(),
             s4: Default::default(),
+            s5: Default::default(),
+            s6: Default::default(),
+            s7: Default::default(),
+            s8: Default::default(),
             download_dir: env::var("XDG_DATA_HOME")
                 .map_or_else(
                     |_| env::var("HOME").expect("") + "/.local/share/" + env!("CARGO_PKG_NAME"),
@@ -126,44 +447,88 @@ impl Default for Config {
                 )
                 .into(),
             likes: Default::default(),
+            favorites_unread: favorites.is_err(),
+            favorites: favorites.unwrap_or_default(),
+            screens: Vec::new(),
         }
     }
 }
 
 fn default_de_list() -> Vec<DesktopEnviroment> {
+    let gnome = || GSettings::new("org.gnome.desktop.background", "picture-uri", true);
     vec![
-        DesktopEnviroment {
-            name: "Budgie".into(),
-            cmd: r#"gsettings set org.gnome.desktop.background picture-uri "file://$WALLPAPER""#.into(),
-        },
-        DesktopEnviroment {
-            name: "Cinnamon".into(),
-            cmd: r#"gsettings set org.cinnamon.desktop.background picture-uri "file://$WALLPAPER""#.into(),
-        },
-        DesktopEnviroment {
-            name: "Deepin".into(),
-            cmd: r#"gsettings set com.deepin.wrap.gnome.desktop.background picture-uri "file://$WALLPAPER""#.into(),
-        },
-        DesktopEnviroment {
-            name: "GNOME".into(),
-            cmd: r#"gsettings set org.gnome.desktop.background picture-uri "file://$WALLPAPER""#.into(),
-        },
-        DesktopEnviroment {
-            name: "LXDE".into(),
-            cmd: r#"pcmanfm --set-wallpaper "$WALLPAPER""#.into(),
-        },
-        DesktopEnviroment {
-            name: "LXQt".into(),
-            cmd: r#"pcmanfm-qt --set-wallpaper "$WALLPAPER""#.into(),
-        },
-        DesktopEnviroment {
-            name: "MATE".into(),
-            cmd: r#"gsettings set org.mate.background picture-filename "$WALLPAPER""#.into(),
-        },
+        DesktopEnviroment::new("Budgie", gnome()),
+        DesktopEnviroment::new(
+            "Cinnamon",
+            GSettings::new("org.cinnamon.desktop.background", "picture-uri", true),
+        ),
+        DesktopEnviroment::new(
+            "Deepin",
+            GSettings::new(
+                "com.deepin.wrap.gnome.desktop.background",
+                "picture-uri",
+                true,
+            ),
+        ),
+        DesktopEnviroment::new("GNOME", gnome()),
+        DesktopEnviroment::new(
+            "Hyprland",
+            FirstInstalled(vec![
+                Argv::new(&[&["swww", "img", "--outputs={monitor}", "{wallpaper}"]]),
+                // An empty monitor of hyprpaper means all of them
+                Argv::new(&[
+                    &["hyprctl", "hyprpaper", "preload", "{wallpaper}"],
+                    &["hyprctl", "hyprpaper", "wallpaper", "{monitor},{wallpaper}"],
+                    &["hyprctl", "hyprpaper", "unload", "unused"],
+                ])
+                .all_monitors(""),
+            ]),
+        ),
+        DesktopEnviroment::new("KDE Plasma", Plasma),
+        DesktopEnviroment::new(
+            "LXDE",
+            Argv::new(&[&["pcmanfm", "--set-wallpaper", "{wallpaper}"]]),
+        ),
+        DesktopEnviroment::new(
+            "LXQt",
+            Argv::new(&[&["pcmanfm-qt", "--set-wallpaper", "{wallpaper}"]]),
+        ),
+        DesktopEnviroment::new(
+            "MATE",
+            GSettings::new("org.mate.background", "picture-filename", false),
+        ),
+        DesktopEnviroment::new(
+            "sway",
+            Argv::new(&[&[
+                "swaymsg",
+                "output",
+                "{monitor}",
+                "bg",
+                "{wallpaper}",
+                "fill",
+            ]])
+            .all_monitors("*"),
+        ),
+        // Window managers without a desktop, the first setter installed draws the root window
+        DesktopEnviroment::new(
+            "X11",
+            FirstInstalled(vec![
+                Argv::new(&[&["feh", "--no-fehbg", "--bg-fill", "{wallpaper}"]]),
+                Argv::new(&[&["xwallpaper", "--zoom", "{wallpaper}"]]),
+                Argv::new(&[&["hsetroot", "-fill", "{wallpaper}"]]),
+            ]),
+        ),
+        DesktopEnviroment::new("XFCE", Xfconf),
     ]
 }
 
 fn current_de() -> usize {
+    let name = detect_de().unwrap_or(OTHER_DE);
+    de_names().iter().position(|n| n == name).unwrap()
+}
+
+/// Name of the desktop in `default_de_list()`
+fn detect_de() -> Option<&'static str> {
     let v: String = (&[
         "XDG_CURRENT_DESKTOP",
         "XDG_SESSION_DESKTOP",
@@ -178,15 +543,87 @@ fn current_de() -> usize {
         .map(|s| s.to_lowercase())
         .next()
         .unwrap_or_default();
-    match &*v {
-        "budgie" => 0,
-        "cinnamon" | "x-cinnamon" => 1,
-        "deepin" => 2,
-        "gnome" | "ubuntu:gnome" | "gnome-xorg" | "gnome-wayland" => 3,
-        "lxde" => 4,
-        "lxqt" => 5,
-        "mate" => 6,
-        _ => 7,
+    // Like `ubuntu:GNOME`, the first known one is used
+    let name = v.split(':').find_map(|desktop| {
+        Some(match desktop {
+            "budgie" | "budgie-desktop" => "Budgie",
+            "cinnamon" | "x-cinnamon" => "Cinnamon",
+            "deepin" => "Deepin",
+            "gnome" | "gnome-xorg" | "gnome-wayland" => "GNOME",
+            "hyprland" => "Hyprland",
+            "kde" | "plasma" | "plasmawayland" | "plasmax11" => "KDE Plasma",
+            "lxde" => "LXDE",
+            "lxqt" => "LXQt",
+            "mate" => "MATE",
+            "sway" => "sway",
+            "xfce" | "xfce4" | "xubuntu" => "XFCE",
+            "i3" | "bspwm" | "openbox" | "awesome" | "dwm" | "xmonad" | "herbstluftwm"
+            | "fluxbox" | "icewm" => "X11",
+            _ => return None,
+        })
+    });
+    if name.is_some() {
+        return name;
+    }
+    // Compositors started without a display manager may not set the variables above
+    if env::var_os("SWAYSOCK").is_some() {
+        Some("sway")
+    } else if env::var_os("HYPRLAND_INSTANCE_SIGNATURE").is_some() {
+        Some("Hyprland")
+    } else {
+        None
+    }
+}
+
+const OTHER_DE: &str = "Other";
+
+/// Names of `Config::de`, indexed by `de_index`
+fn de_names() -> Vec<String> {
+    default_de_list()
+        .iter()
+        .map(|de| String::from_utf16_lossy(de.name.to_slice()))
+        .chain(Some(OTHER_DE.to_owned()))
+        .collect()
+}
+
+// Save the name of the desktop instead of its index, so adding desktops doesn't change it
+mod de_name {
+    use super::*;
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    /// The list indexes in old configs are of
+    const OLD_DE_LIST: [&str; 8] = [
+        "Budgie", "Cinnamon", "Deepin", "GNOME", "LXDE", "LXQt", "MATE", OTHER_DE,
+    ];
+
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum NameOrIndex {
+        Name(String),
+        Index(usize),
+    }
+
+    pub fn serialize<S>(index: &usize, ser: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        de_names()[*index].serialize(ser)
+    }
+
+    pub fn deserialize<'de, D>(de: D) -> Result<usize, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let name = match NameOrIndex::deserialize(de)? {
+            NameOrIndex::Name(name) => name,
+            NameOrIndex::Index(index) => OLD_DE_LIST.get(index).unwrap_or(&OTHER_DE).to_string(),
+        };
+        let names = de_names();
+        // Unknown ones are from newer versions
+        Ok(names
+            .iter()
+            .position(|n| *n == name)
+            .unwrap_or(names.len() - 1))
     }
 }
 
@@ -195,6 +632,10 @@ pub struct AutoChangeConfig {
     pub enable: qt_property!(bool),
     pub interval: qt_property!(u32),
     pub mode: qt_property!(u8),
+    /// A different wallpaper on each monitor, if the setter of the desktop `supports_monitors`,
+    /// like the custom command does with `$MONITOR`
+    #[serde(default)]
+    pub per_monitor: qt_property!(bool),
 }
 
 impl Default for AutoChangeConfig {
@@ -203,6 +644,7 @@ impl Default for AutoChangeConfig {
             enable: false,
             interval: 5,
             mode: 0,
+            per_monitor: false,
         }
     }
 }
@@ -217,6 +659,14 @@ pub struct Resolution {
     pub download_index: qt_property!(usize),
     #[serde(default)] // for compatibility
     pub original: qt_property!(bool),
+    /// Choose from `download` for the screens, instead of `download_index`.
+    /// Off for old configs, whose resolution was chosen by the user
+    #[serde(default)]
+    pub auto_detect: qt_property!(bool),
+    /// Make images the size of the screen: 0 off, 1 fill, 2 fit, 3 smart crop.
+    /// Off for old configs
+    #[serde(default)]
+    pub scale: qt_property!(u8),
 }
 
 impl Default for Resolution {
@@ -227,6 +677,8 @@ impl Default for Resolution {
             download: default_download(),
             download_index: 0,
             original: false,
+            auto_detect: true,
+            scale: 3,
         }
     }
 }
@@ -244,12 +696,65 @@ fn default_download() -> QVariantList {
     ])
 }
 
-#[derive(Serialize, Deserialize)]
+/// Empty ones are the defaults
+#[derive(QGadget, Clone, Default, Serialize, Deserialize)]
+pub struct NetworkConfig {
+    /// Of bohan's mirror, `https://leanapi.bohan.co`
+    #[serde(with = "qstring", default)]
+    pub api_base: qt_property!(QString),
+    /// Of bohan's mirror, `https://wpdn.bohan.co`
+    #[serde(with = "qstring", default)]
+    pub cdn_base: qt_property!(QString),
+    /// `http://`, `https://`, or `socks5://` with an optional `user:password@`
+    #[serde(with = "qstring", default)]
+    pub proxy: qt_property!(QString),
+}
+
 pub struct DesktopEnviroment {
-    #[serde(with = "qstring")]
     pub name: QString,
-    #[serde(with = "qstring")]
+    /// What `setter` runs, only the one of "Other" can be changed
     pub cmd: QString,
+    pub setter: Arc<dyn WallpaperSetter>,
+}
+
+impl DesktopEnviroment {
+    fn new(name: &str, setter: impl WallpaperSetter + 'static) -> Self {
+        DesktopEnviroment {
+            name: name.into(),
+            cmd: setter.to_string().into(),
+            setter: Arc::new(setter),
+        }
+    }
+
+    /// "Other", running `cmd` by shell
+    pub fn custom(cmd: &str) -> Self {
+        Self::new(OTHER_DE, Shell::new(cmd))
+    }
+
+    fn is_custom(&self) -> bool {
+        String::from_utf16_lossy(self.name.to_slice()) == OTHER_DE
+    }
+}
+
+// Save the name of the source instead of its index
+mod source_kind {
+    use super::*;
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S>(index: &usize, ser: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        SourceKind::ALL[*index].serialize(ser)
+    }
+
+    pub fn deserialize<'de, D>(de: D) -> Result<usize, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let kind = SourceKind::deserialize(de)?;
+        Ok(SourceKind::ALL.iter().position(|k| *k == kind).unwrap())
+    }
 }
 
 mod qstring {
@@ -283,7 +788,8 @@ impl MutListItem for DesktopEnviroment {
     fn set(&mut self, value: &QVariant, idx: i32) -> bool {
         match idx {
             0 => <_>::from_qvariant(value.clone()).map(|v| self.name = v),
-            1 => <_>::from_qvariant(value.clone()).map(|v| self.cmd = v),
+            1 if self.is_custom() => <QString>::from_qvariant(value.clone())
+                .map(|v| *self = Self::custom(&String::from_utf16_lossy(v.to_slice()))),
             _ => None,
         }
         .is_some()
@@ -0,0 +1,153 @@
+//! `org.biying.Wallpaper`, for desktop widgets and scripts
+
+use std::sync::{mpsc, Arc};
+use std::thread;
+use std::time::Duration;
+
+use dbus::arg::{PropMap, RefArg, Variant};
+use dbus::blocking::stdintf::org_freedesktop_dbus::PropertiesPropertiesChanged;
+use dbus::blocking::LocalConnection;
+use dbus::channel::Sender;
+use dbus::message::SignalArgs;
+use dbus::strings::{Interface, Path};
+use dbus_tree::{Access, Factory};
+
+use crate::implementation::{self, CurrentWallpaper};
+use crate::scheduler;
+use crate::systray::{self, Cmd};
+
+const NAME: &str = "org.biying.Wallpaper";
+const PATH: &str = "/org/biying/Wallpaper";
+
+pub fn run_in_background() {
+    thread::spawn(|| {
+        if let Err(e) = run() {
+            eprintln!("D-Bus service stopped: {}", e);
+        }
+    });
+}
+
+fn current() -> Option<CurrentWallpaper> {
+    let cache_dir = scheduler::snapshot()?.cache_dir;
+    implementation::current_wallpaper(&cache_dir)
+}
+
+fn run() -> Result<(), dbus::Error> {
+    let conn = LocalConnection::new_session()?;
+    conn.request_name(NAME, false, true, false)?;
+
+    let (changed_tx, changed_rx) = mpsc::channel();
+    implementation::on_wallpaper_changed(move |current| {
+        changed_tx.send(current.clone()).unwrap_or_default()
+    });
+
+    let f = Factory::new_fn::<()>();
+    let wallpaper_changed = Arc::new(
+        f.signal("WallpaperChanged", ())
+            .sarg::<&str, _>("id")
+            .sarg::<&str, _>("path"),
+    );
+    let iface = f
+        .interface(NAME, ())
+        .add_m(f.method("Next", (), |m| {
+            systray::emit_cmd(Cmd::Next);
+            Ok(vec![m.msg.method_return()])
+        }))
+        .add_m(
+            f.method("SetById", (), |m| {
+                let id: &str = m.msg.read1()?;
+                systray::emit_cmd(Cmd::SetById(id.to_owned()));
+                Ok(vec![m.msg.method_return()])
+            })
+            .inarg::<&str, _>("id"),
+        )
+        .add_m(f.method("ToggleLikeCurrent", (), |m| {
+            systray::emit_cmd(Cmd::ToggleLike);
+            Ok(vec![m.msg.method_return()])
+        }))
+        .add_m(f.method("Open", (), |m| {
+            systray::emit_cmd(Cmd::Open);
+            Ok(vec![m.msg.method_return()])
+        }))
+        .add_p(
+            f.property::<&str, _>("CurrentId", ())
+                .access(Access::Read)
+                .on_get(|i, _| {
+                    i.append(current().map(|c| c.id).unwrap_or_default());
+                    Ok(())
+                }),
+        )
+        .add_p(
+            f.property::<&str, _>("CurrentPath", ())
+                .access(Access::Read)
+                .on_get(|i, _| {
+                    i.append(current().map(|c| c.path).unwrap_or_default());
+                    Ok(())
+                }),
+        )
+        .add_p(
+            f.property::<bool, _>("AutoChangeEnabled", ())
+                .access(Access::Read)
+                .on_get(|i, _| {
+                    i.append(scheduler::snapshot().map_or(false, |s| s.auto_change.enable));
+                    Ok(())
+                }),
+        )
+        .add_s(wallpaper_changed.clone());
+    let tree = f
+        .tree(())
+        .add(f.object_path(PATH, ()).introspectable().add(iface));
+    tree.start_receive(&conn);
+
+    let path = Path::from(PATH);
+    let iface = Interface::from(NAME);
+    // Every change of the config reaches the scheduler, checked along with D-Bus messages
+    let snapshots = scheduler::subscribe();
+    let auto_change = || {
+        snapshots
+            .borrow()
+            .as_ref()
+            .map_or(false, |s| s.auto_change.enable)
+    };
+    let mut auto_change_enabled = auto_change();
+    loop {
+        conn.process(Duration::from_millis(500))?;
+        for current in changed_rx.try_iter() {
+            let signal = wallpaper_changed
+                .msg(&path, &iface)
+                .append2(&*current.id, &*current.path);
+            conn.send(signal).unwrap_or_default();
+
+            let mut changed_properties = PropMap::new();
+            changed_properties.insert(
+                "CurrentId".into(),
+                Variant(Box::new(current.id) as Box<dyn RefArg>),
+            );
+            changed_properties.insert(
+                "CurrentPath".into(),
+                Variant(Box::new(current.path) as Box<dyn RefArg>),
+            );
+            emit_properties_changed(&conn, &path, changed_properties);
+        }
+
+        if auto_change() != auto_change_enabled {
+            auto_change_enabled = !auto_change_enabled;
+            let mut changed_properties = PropMap::new();
+            changed_properties.insert(
+                "AutoChangeEnabled".into(),
+                Variant(Box::new(auto_change_enabled) as Box<dyn RefArg>),
+            );
+            emit_properties_changed(&conn, &path, changed_properties);
+        }
+    }
+}
+
+fn emit_properties_changed(conn: &LocalConnection, path: &Path, changed_properties: PropMap) {
+    let properties_changed = PropertiesPropertiesChanged {
+        interface_name: NAME.into(),
+        changed_properties,
+        invalidated_properties: Vec::new(),
+    };
+    conn.send(properties_changed.to_emit_message(path))
+        .unwrap_or_default();
+}
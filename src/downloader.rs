@@ -0,0 +1,269 @@
+//! All image downloads go through here, at most `MAX_DOWNLOADS` at a time.
+//! Asking for a file that is being downloaded joins that download instead
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use failure::{self, Fail};
+use futures::future::{AbortHandle, Abortable};
+use lazy_static::lazy_static;
+use reqwest;
+use tokio::{
+    fs::{File, OpenOptions},
+    io::AsyncWriteExt,
+    sync::{watch, Semaphore},
+    time::{error::Elapsed, sleep, timeout},
+};
+
+use crate::async_utils;
+use crate::error::Error;
+use crate::http;
+
+const MAX_DOWNLOADS: usize = 3;
+
+lazy_static! {
+    static ref SLOTS: Semaphore = Semaphore::new(MAX_DOWNLOADS);
+    /// Downloads queued or running, by their output file
+    static ref TASKS: Mutex<HashMap<PathBuf, Task>> = Mutex::default();
+}
+
+#[derive(Clone, Copy, Default, PartialEq, Debug)]
+pub struct Progress {
+    pub received: u64,
+    /// `None` if the server didn't tell
+    pub total: Option<u64>,
+}
+
+impl Progress {
+    /// From 0 to 1, always 0 if the size is unknown
+    pub fn ratio(&self) -> f64 {
+        match self.total {
+            Some(total) if total > 0 => self.received as f64 / total as f64,
+            _ => 0.0,
+        }
+    }
+}
+
+/// Cloneable, so every one waiting for the same download gets it
+#[derive(Fail, Clone, Debug)]
+pub enum DownloadError {
+    #[fail(display = "Download cancelled")]
+    Cancelled,
+    #[fail(display = "{}", _0)]
+    Failed(Error),
+}
+
+impl From<failure::Error> for DownloadError {
+    fn from(e: failure::Error) -> Self {
+        DownloadError::Failed(e.into())
+    }
+}
+
+#[derive(Clone)]
+enum State {
+    Queued,
+    Running(Progress),
+    Done(Result<bool, DownloadError>),
+}
+
+/// Checks a downloaded file before it's renamed to the output
+type Check = Arc<dyn Fn(&Path) -> Result<(), failure::Error> + Send + Sync>;
+
+struct Task {
+    state: watch::Receiver<State>,
+    abort: AbortHandle,
+}
+
+/// A queued or running download
+pub struct Download {
+    state: watch::Receiver<State>,
+}
+
+impl Download {
+    /// Wait for the next progress, `None` once it's finished or cancelled
+    pub async fn progress(&mut self) -> Option<Progress> {
+        loop {
+            // Dropped without being done, it's cancelled
+            self.state.changed().await.ok()?;
+            match &*self.state.borrow() {
+                State::Queued => continue,
+                State::Running(progress) => return Some(*progress),
+                State::Done(_) => return None,
+            }
+        }
+    }
+
+    /// Wait for it to finish, returns `false` if the file is not found on the server
+    pub async fn finish(mut self) -> Result<bool, DownloadError> {
+        while self.progress().await.is_some() {}
+        let state = self.state.borrow();
+        match &*state {
+            State::Done(r) => r.clone(),
+            _ => Err(DownloadError::Cancelled),
+        }
+    }
+}
+
+/// Queue a download of `url` to `output`, or join the one already queued. See `download_file`
+pub fn download(
+    url: &str,
+    output: &Path,
+    check: impl Fn(&Path) -> Result<(), failure::Error> + Send + Sync + 'static,
+) -> Download {
+    let mut tasks = TASKS.lock().unwrap();
+    if let Some(task) = tasks.get(output) {
+        return Download {
+            state: task.state.clone(),
+        };
+    }
+
+    let (tx, rx) = watch::channel(State::Queued);
+    let (abort, registration) = AbortHandle::new_pair();
+    tasks.insert(
+        output.to_owned(),
+        Task {
+            state: rx.clone(),
+            abort,
+        },
+    );
+    let url = url.to_owned();
+    let output = output.to_owned();
+    let check: Check = Arc::new(check);
+    async_utils::spawn(async move {
+        let download = async {
+            let _slot = SLOTS.acquire().await.expect("download slots closed");
+            let mut attempt = 0;
+            loop {
+                let on_progress = |progress| tx.send(State::Running(progress)).unwrap_or_default();
+                match download_file(&url, &output, check.clone(), on_progress).await {
+                    // Resumed from where it stopped
+                    Err(e) if is_transient(&e) && attempt < http::MAX_RETRIES => {
+                        sleep(http::backoff(attempt)).await;
+                        attempt += 1;
+                    }
+                    r => break r,
+                }
+            }
+        };
+        let r = Abortable::new(download, registration).await;
+        TASKS.lock().unwrap().remove(&output);
+        if let Ok(r) = r {
+            tx.send(State::Done(r.map_err(Into::into)))
+                .unwrap_or_default();
+        }
+    });
+    Download { state: rx }
+}
+
+/// Network errors, or the server is temporarily down
+fn is_transient(e: &failure::Error) -> bool {
+    e.downcast_ref::<reqwest::Error>()
+        .map_or(false, http::is_transient)
+        || e.downcast_ref::<Elapsed>().is_some()
+        || e.downcast_ref::<Error>().map_or(false, Error::is_network)
+}
+
+/// Stop downloading `output`, its partial file is kept to be resumed later.
+/// Returns `false` if it's not being downloaded
+pub fn cancel(output: &Path) -> bool {
+    match TASKS.lock().unwrap().get(output) {
+        Some(task) => {
+            task.abort.abort();
+            true
+        }
+        None => false,
+    }
+}
+
+/// Unfinished downloads of `{file}` are kept in `{file}.part`
+pub const PARTIAL_SUFFIX: &str = ".part";
+
+/// Download `url` to `output` through a partial file, which is resumed if it's there,
+/// and only renamed to `output` when it's complete and passes `check`,
+/// it's deleted otherwise. Returns `false` if `url` is not found
+async fn download_file(
+    url: &str,
+    output: &Path,
+    check: Check,
+    mut on_progress: impl FnMut(Progress),
+) -> Result<bool, failure::Error> {
+    use reqwest::header::{CONTENT_RANGE, RANGE};
+    use reqwest::StatusCode;
+
+    let mut partial = output.as_os_str().to_owned();
+    partial.push(PARTIAL_SUFFIX);
+    let partial = PathBuf::from(partial);
+
+    let client = http::download_client();
+    let offset = fs::metadata(&partial).map(|m| m.len()).unwrap_or(0);
+    let mut request = client.get(url);
+    if offset > 0 {
+        request = request.header(RANGE, format!("bytes={}-", offset));
+    }
+    // Sent once, `download` retries and resumes
+    let mut r = request.send().await?;
+    if r.status() == StatusCode::RANGE_NOT_SATISFIABLE {
+        // The partial file is no longer of the same image, start over
+        fs::remove_file(&partial)?;
+        r = client.get(url).send().await?;
+    }
+    if http::is_transient_status(r.status()) {
+        // A `reqwest::Error` with the status, so it's retried
+        return Err(r.error_for_status().unwrap_err().into());
+    }
+    if r.status() == StatusCode::NOT_FOUND {
+        return Ok(false);
+    }
+    if !r.status().is_success() {
+        return Err(Error::Server(format!("{} from {}", r.status(), url)).into());
+    }
+
+    // Servers ignoring `Range` send the whole file again
+    let resumed = r.status() == StatusCode::PARTIAL_CONTENT
+        && r.headers()
+            .get(CONTENT_RANGE)
+            .and_then(|v| v.to_str().ok())
+            .map_or(false, |v| v.starts_with(&format!("bytes {}-", offset)));
+    let (mut file, start) = if resumed {
+        let file = OpenOptions::new().append(true).open(&partial).await?;
+        (file, offset)
+    } else {
+        (File::create(&partial).await?, 0)
+    };
+    let expected = r.content_length().map(|len| start + len);
+    let mut written = start;
+    on_progress(Progress {
+        received: written,
+        total: expected,
+    });
+    while let Some(chunk) = timeout(http::READ_TIMEOUT, r.chunk()).await?? {
+        file.write_all(&chunk).await?;
+        written += chunk.len() as u64;
+        on_progress(Progress {
+            received: written,
+            total: expected,
+        });
+    }
+    file.flush().await?;
+    drop(file);
+
+    if let Some(expected) = expected {
+        if written != expected {
+            if written > expected {
+                fs::remove_file(&partial)?;
+            }
+            let msg = format!("got {} of {} bytes from {}", written, expected, url);
+            return Err(Error::Network(msg).into());
+        }
+    }
+    // Decoding takes a while, don't block other downloads
+    let checked = partial.clone();
+    if let Err(e) = tokio::task::spawn_blocking(move || check(&checked)).await? {
+        fs::remove_file(&partial)?;
+        return Err(Error::Decode(format!("{} from {}", e, url)).into());
+    }
+    fs::rename(&partial, output)?;
+    Ok(true)
+}
@@ -0,0 +1,93 @@
+//! Errors shown to the user, by what went wrong, so the UI can tell if trying again may help
+
+use std::io;
+
+use failure::{self, Fail};
+use reqwest;
+use tokio::time::error::Elapsed;
+
+use crate::downloader::DownloadError;
+use crate::source::ServerError;
+
+#[derive(Fail, Clone, Debug)]
+pub enum Error {
+    /// Can't reach the server, or it's too slow
+    #[fail(display = "Network error: {}", _0)]
+    Network(String),
+    /// The server is reached, but it says no
+    #[fail(display = "Server error: {}", _0)]
+    Server(String),
+    #[fail(display = "File error: {}", _0)]
+    Fs(String),
+    /// Broken images, or responses can't be understood
+    #[fail(display = "Broken data: {}", _0)]
+    Decode(String),
+    /// The command to set wallpaper failed
+    #[fail(display = "Failed to set wallpaper: {}", _0)]
+    Command(String),
+    #[fail(display = "{}", _0)]
+    Other(String),
+}
+
+impl Error {
+    /// For QML
+    pub fn category(&self) -> &'static str {
+        match self {
+            Error::Network(_) => "network",
+            Error::Server(_) => "server",
+            Error::Fs(_) => "fs",
+            Error::Decode(_) => "decode",
+            Error::Command(_) => "command",
+            Error::Other(_) => "other",
+        }
+    }
+
+    /// Trying again later may work
+    pub fn retryable(&self) -> bool {
+        matches!(self, Error::Network(_) | Error::Server(_))
+    }
+
+    pub fn is_network(&self) -> bool {
+        matches!(self, Error::Network(_))
+    }
+}
+
+impl From<&failure::Error> for Error {
+    fn from(e: &failure::Error) -> Error {
+        let msg = e.to_string();
+        if let Some(e) = e.downcast_ref::<Error>() {
+            e.clone()
+        } else if let Some(e) = e.downcast_ref::<DownloadError>() {
+            match e {
+                DownloadError::Failed(e) => e.clone(),
+                DownloadError::Cancelled => Error::Other(msg),
+            }
+        } else if let Some(e) = e.downcast_ref::<reqwest::Error>() {
+            if e.is_status() {
+                Error::Server(msg)
+            } else if e.is_decode() {
+                Error::Decode(msg)
+            } else {
+                Error::Network(msg)
+            }
+        } else if e.downcast_ref::<Elapsed>().is_some() {
+            Error::Network(msg)
+        } else if e.downcast_ref::<ServerError>().is_some() {
+            Error::Server(msg)
+        } else if e.downcast_ref::<io::Error>().is_some() {
+            Error::Fs(msg)
+        } else if e.downcast_ref::<image::ImageError>().is_some()
+            || e.downcast_ref::<serde_json::Error>().is_some()
+        {
+            Error::Decode(msg)
+        } else {
+            Error::Other(msg)
+        }
+    }
+}
+
+impl From<failure::Error> for Error {
+    fn from(e: failure::Error) -> Error {
+        Error::from(&e)
+    }
+}
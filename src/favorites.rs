@@ -0,0 +1,290 @@
+//! Favorite wallpapers, with everything needed to show and set them,
+//! so they are kept even if their source forgets them
+
+use std::fs::{self, File};
+use std::io::{prelude::*, SeekFrom};
+use std::path::Path;
+
+use chrono::prelude::*;
+use failure::format_err;
+use serde::{Deserialize, Serialize};
+
+use crate::downloader::PARTIAL_SUFFIX;
+use crate::implementation::check_image;
+use crate::scale;
+use crate::source::{ImageMeta, RawImage, SourceKind, LOCAL_ID_PREFIX};
+
+const FAVORITES_FILE: &str = "favorites.json";
+/// Directory of images in exported archives
+const IMAGES_DIR: &str = "images";
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Favorite {
+    pub source: SourceKind,
+    #[serde(flatten)]
+    pub image: RawImage,
+    pub metas: Vec<ImageMeta>,
+    pub liked_at: DateTime<Utc>,
+}
+
+impl Favorite {
+    pub fn new(image: &RawImage) -> Self {
+        Favorite {
+            source: image.source,
+            image: image.clone(),
+            metas: image.metas.clone(),
+            liked_at: Utc::now(),
+        }
+    }
+
+    pub fn id(&self) -> &str {
+        &self.image.object_id
+    }
+
+    pub fn to_raw(&self) -> RawImage {
+        RawImage {
+            metas: self.metas.clone(),
+            source: self.source,
+            ..self.image.clone()
+        }
+    }
+
+    /// Migrated from a bare ID, the rest should be filled in from its source
+    pub fn is_complete(&self) -> bool {
+        !self.image.urlbase.is_empty()
+    }
+}
+
+/// Newest liked first
+#[derive(Serialize, Deserialize, Clone, Default, Debug)]
+#[serde(transparent)]
+pub struct Favorites(Vec<Favorite>);
+
+impl Favorites {
+    /// Open the favorites in `dir`, IDs in `likes` of old configs are added if missing
+    pub fn open(dir: &Path, likes: Vec<String>) -> Result<Self, failure::Error> {
+        let path = dir.join(FAVORITES_FILE);
+        let mut favorites: Favorites = if path.exists() {
+            match serde_json::from_slice(&fs::read(&path)?) {
+                Ok(favorites) => favorites,
+                Err(e) => {
+                    // Don't overwrite it on next save
+                    let backup = path.with_extension("json.bak");
+                    eprintln!(
+                        "Broken {}, moved to {}: {}",
+                        path.display(),
+                        backup.display(),
+                        e
+                    );
+                    fs::rename(&path, &backup)?;
+                    Favorites::default()
+                }
+            }
+        } else {
+            Favorites::default()
+        };
+        let liked_at = Utc::now();
+        for id in likes {
+            if favorites.contains(&id) {
+                continue;
+            }
+            // Bare IDs are all from LeanCloud, or the local folder
+            let source = if id.starts_with(LOCAL_ID_PREFIX) {
+                SourceKind::Local
+            } else {
+                SourceKind::LeanCloud
+            };
+            favorites.0.push(Favorite {
+                source,
+                image: RawImage {
+                    name: id.clone(),
+                    object_id: id,
+                    source,
+                    ..RawImage::default()
+                },
+                metas: Vec::new(),
+                liked_at,
+            });
+        }
+        Ok(favorites)
+    }
+
+    pub fn save(&self, dir: &Path) -> Result<(), failure::Error> {
+        if !dir.exists() {
+            fs::create_dir_all(dir)?;
+        }
+        let tmp = dir.join(FAVORITES_FILE.to_owned() + ".tmp");
+        fs::write(&tmp, serde_json::to_vec_pretty(self)?)?;
+        fs::rename(tmp, dir.join(FAVORITES_FILE))?;
+        Ok(())
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Favorite> {
+        self.0.iter()
+    }
+
+    pub fn page(&self, offset: usize, limit: usize) -> &[Favorite] {
+        let start = offset.min(self.0.len());
+        let end = (offset + limit).min(self.0.len());
+        &self.0[start..end]
+    }
+
+    pub fn get(&self, id: &str) -> Option<&Favorite> {
+        self.0.iter().find(|f| f.id() == id)
+    }
+
+    pub fn contains(&self, id: &str) -> bool {
+        self.get(id).is_some()
+    }
+
+    /// Add it to the front, does nothing if it's already there
+    pub fn insert(&mut self, image: &RawImage) {
+        if !self.contains(&image.object_id) {
+            self.0.insert(0, Favorite::new(image));
+        }
+    }
+
+    pub fn remove(&mut self, id: &str) -> bool {
+        let len = self.0.len();
+        self.0.retain(|f| f.id() != id);
+        self.0.len() != len
+    }
+
+    /// Add it, or remove it if it's already there. Returns `true` if it's favorited now
+    pub fn toggle(&mut self, image: &RawImage) -> bool {
+        if self.remove(&image.object_id) {
+            false
+        } else {
+            self.insert(image);
+            true
+        }
+    }
+
+    /// Refresh the metadata of a favorite, keeping the time it was liked
+    pub fn update(&mut self, image: &RawImage) {
+        if let Some(f) = self.0.iter_mut().find(|f| f.id() == image.object_id) {
+            *f = Favorite {
+                liked_at: f.liked_at,
+                ..Favorite::new(image)
+            };
+        }
+    }
+
+    /// Write all favorites to `path`, as a JSON file,
+    /// or a tar archive with their images in `download_dir` if it's given
+    pub fn export(&self, path: &Path, download_dir: Option<&Path>) -> Result<(), failure::Error> {
+        let json = serde_json::to_vec_pretty(self)?;
+        let download_dir = match download_dir {
+            Some(dir) => dir,
+            None => return Ok(fs::write(path, json)?),
+        };
+
+        let mut archive = tar::Builder::new(File::create(path)?);
+        let mut header = tar::Header::new_gnu();
+        header.set_size(json.len() as u64);
+        header.set_mode(0o644);
+        header.set_mtime(Utc::now().timestamp() as u64);
+        header.set_cksum();
+        archive.append_data(&mut header, FAVORITES_FILE, &*json)?;
+        // Downloaded images are named `{id}_{resolution}.jpg`,
+        // local ones aren't included, they are not ours
+        for entry in fs::read_dir(download_dir)? {
+            let entry = entry?;
+            let name = entry.file_name();
+            let name = match name.to_str() {
+                Some(name) => name,
+                None => continue,
+            };
+            if name.ends_with(PARTIAL_SUFFIX) || scale::is_scaled(name) {
+                continue;
+            }
+            let id = name.split('_').next().unwrap_or_default();
+            if self.contains(id) && entry.file_type()?.is_file() {
+                let mut file = File::open(entry.path())?;
+                archive.append_file(Path::new(IMAGES_DIR).join(name), &mut file)?;
+            }
+        }
+        archive.finish()?;
+        Ok(())
+    }
+
+    /// Merge favorites from a file written by `export`, images in it are extracted to
+    /// `download_dir`. Returns how many favorites are new
+    pub fn import(&mut self, path: &Path, download_dir: &Path) -> Result<usize, failure::Error> {
+        let mut file = File::open(path)?;
+        let mut header = Vec::new();
+        (&mut file).take(512).read_to_end(&mut header)?;
+        file.seek(SeekFrom::Start(0))?;
+        // Of both ustar and GNU tar
+        let is_tar = header.get(257..262) == Some(&b"ustar"[..]);
+
+        let imported: Favorites = if !is_tar {
+            let mut json = String::new();
+            file.read_to_string(&mut json)?;
+            serde_json::from_str(json.trim_start_matches('\u{feff}'))?
+        } else {
+            let mut favorites = None;
+            let mut archive = tar::Archive::new(file);
+            for entry in archive.entries()? {
+                let mut entry = entry?;
+                let entry_path = entry.path()?.into_owned();
+                if entry_path == Path::new(FAVORITES_FILE) {
+                    let mut json = Vec::new();
+                    entry.read_to_end(&mut json)?;
+                    favorites = Some(serde_json::from_slice(&json)?);
+                } else if let Ok(name) = entry_path.strip_prefix(IMAGES_DIR) {
+                    // No links, directories or devices
+                    if entry.header().entry_type() != tar::EntryType::Regular {
+                        continue;
+                    }
+                    // Only plain file names, nothing outside `download_dir`
+                    let name = match name.file_name().and_then(|n| n.to_str()) {
+                        Some(file_name) if file_name == name.as_os_str() => file_name,
+                        _ => continue,
+                    };
+                    // Named `{id}_{resolution}.jpg`
+                    let resolution = match name
+                        .strip_suffix(".jpg")
+                        .and_then(|stem| stem.rsplit_once('_'))
+                    {
+                        Some((_, resolution)) => resolution,
+                        None => continue,
+                    };
+                    let output = download_dir.join(name);
+                    if !output.exists() {
+                        fs::create_dir_all(download_dir)?;
+                        // Checked like a download, broken ones are downloaded again when needed
+                        let partial = download_dir.join(name.to_owned() + PARTIAL_SUFFIX);
+                        entry.unpack(&partial)?;
+                        match check_image(&partial, resolution) {
+                            Ok(()) => fs::rename(&partial, &output)?,
+                            Err(e) => {
+                                eprintln!("Skipped broken {}: {}", name, e);
+                                fs::remove_file(&partial)?;
+                            }
+                        }
+                    }
+                }
+            }
+            favorites.ok_or_else(|| format_err!("No {} in the archive", FAVORITES_FILE))?
+        };
+
+        let mut count = 0;
+        for favorite in imported.0 {
+            if !self.contains(favorite.id()) {
+                self.0.push(favorite);
+                count += 1;
+            }
+        }
+        self.0.sort_by(|a, b| b.liked_at.cmp(&a.liked_at));
+        Ok(count)
+    }
+}
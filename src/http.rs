@@ -0,0 +1,259 @@
+//! Every HTTP request goes through here, so they all have timeouts,
+//! and are retried on network errors and server errors
+
+use std::sync::RwLock;
+use std::time::Duration;
+
+use chrono::prelude::*;
+use lazy_static::lazy_static;
+use rand::Rng;
+use reqwest::{header::RETRY_AFTER, RequestBuilder, Response, StatusCode};
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+/// For API calls, downloads only have `READ_TIMEOUT` between chunks
+const TIMEOUT: Duration = Duration::from_secs(30);
+pub const READ_TIMEOUT: Duration = Duration::from_secs(30);
+pub const MAX_RETRIES: u32 = 3;
+const BACKOFF_BASE: Duration = Duration::from_millis(500);
+/// Longer `Retry-After`s are not waited for
+const MAX_RETRY_AFTER: Duration = Duration::from_secs(60);
+
+lazy_static! {
+    /// Empty for no proxy, other than the one in environment variables
+    static ref PROXY: RwLock<String> = RwLock::default();
+    static ref CLIENT: RwLock<reqwest::Client> = RwLock::new(build_client());
+    static ref DOWNLOAD_CLIENT: RwLock<reqwest::Client> = RwLock::new(build_download_client());
+}
+
+/// Clients are cheap to clone, they share the connection pool
+pub fn client() -> reqwest::Client {
+    CLIENT.read().unwrap().clone()
+}
+
+/// Without the total timeout, images may take longer
+pub fn download_client() -> reqwest::Client {
+    DOWNLOAD_CLIENT.read().unwrap().clone()
+}
+
+/// Rebuild the clients if `proxy` is changed
+pub fn set_proxy(proxy: &str) {
+    if *PROXY.read().unwrap() == proxy {
+        return;
+    }
+    *PROXY.write().unwrap() = proxy.to_owned();
+    *CLIENT.write().unwrap() = build_client();
+    *DOWNLOAD_CLIENT.write().unwrap() = build_download_client();
+}
+
+fn build_client() -> reqwest::Client {
+    with_proxy(reqwest::Client::builder())
+        .connect_timeout(CONNECT_TIMEOUT)
+        .timeout(TIMEOUT)
+        .build()
+        .expect("build client")
+}
+
+fn build_download_client() -> reqwest::Client {
+    with_proxy(reqwest::Client::builder())
+        .connect_timeout(CONNECT_TIMEOUT)
+        .build()
+        .expect("build client")
+}
+
+fn with_proxy(builder: reqwest::ClientBuilder) -> reqwest::ClientBuilder {
+    let proxy = PROXY.read().unwrap();
+    if proxy.is_empty() {
+        return builder;
+    }
+    match reqwest::Proxy::all(&*proxy) {
+        Ok(proxy) => builder.proxy(proxy),
+        Err(e) => {
+            eprintln!("Invalid proxy {}: {}", proxy, e);
+            builder
+        }
+    }
+}
+
+/// Send the request, retry up to `MAX_RETRIES` times if it fails with a network error,
+/// 5xx or 429. The last response is returned as is, even if it's an error
+pub async fn send(request: RequestBuilder) -> reqwest::Result<Response> {
+    let mut attempt = 0;
+    loop {
+        // Requests with streaming bodies can't be retried
+        let retry = match request.try_clone() {
+            Some(retry) if attempt < MAX_RETRIES => retry,
+            _ => return request.send().await,
+        };
+        let delay = match retry.send().await {
+            Ok(r) if is_transient_status(r.status()) => {
+                retry_after(&r).unwrap_or_else(|| backoff(attempt))
+            }
+            Ok(r) => return Ok(r),
+            Err(e) if is_transient(&e) => backoff(attempt),
+            Err(e) => return Err(e),
+        };
+        tokio::time::sleep(delay).await;
+        attempt += 1;
+    }
+}
+
+/// Network errors that may go away if tried again
+pub fn is_transient(e: &reqwest::Error) -> bool {
+    e.is_timeout()
+        || e.is_connect()
+        || e.is_request()
+        || e.is_body()
+        || e.status().map_or(false, is_transient_status)
+}
+
+/// 5xx and 429
+pub fn is_transient_status(status: StatusCode) -> bool {
+    status.is_server_error() || status == StatusCode::TOO_MANY_REQUESTS
+}
+
+/// Exponential backoff with full jitter, `attempt` starts from 0
+pub fn backoff(attempt: u32) -> Duration {
+    let max = BACKOFF_BASE * 2u32.pow(attempt);
+    rand::thread_rng().gen_range(BACKOFF_BASE / 2..=max)
+}
+
+/// `Retry-After` in seconds, or an HTTP date
+fn retry_after(r: &Response) -> Option<Duration> {
+    let value = r.headers().get(RETRY_AFTER)?.to_str().ok()?;
+    let delay = match value.parse::<u64>() {
+        Ok(secs) => Duration::from_secs(secs),
+        Err(_) => {
+            let date = DateTime::parse_from_rfc2822(value).ok()?;
+            (date.with_timezone(&Utc) - Utc::now())
+                .to_std()
+                .unwrap_or_default()
+        }
+    };
+    Some(delay.min(MAX_RETRY_AFTER))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::time::Instant;
+
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    const OK: Option<&str> = Some("HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\nok");
+    const UNAVAILABLE: Option<&str> =
+        Some("HTTP/1.1 503 Service Unavailable\r\nContent-Length: 0\r\n\r\n");
+    const NOT_FOUND: Option<&str> = Some("HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\n\r\n");
+    const TOO_MANY: Option<&str> =
+        Some("HTTP/1.1 429 Too Many Requests\r\nRetry-After: 1\r\nContent-Length: 0\r\n\r\n");
+
+    /// A server answering the n-th request with `responses[n]`, the last one is repeated.
+    /// `None` never answers. Returns its URL and how many requests it got
+    async fn serve(responses: Vec<Option<&'static str>>) -> (String, Arc<AtomicUsize>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+        let count = Arc::new(AtomicUsize::new(0));
+        let counter = count.clone();
+        tokio::spawn(async move {
+            loop {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                let n = counter.fetch_add(1, Ordering::SeqCst);
+                let response = responses[n.min(responses.len() - 1)];
+                tokio::spawn(async move {
+                    let mut request = Vec::new();
+                    let mut buf = [0; 1024];
+                    while !request.ends_with(b"\r\n\r\n") {
+                        match socket.read(&mut buf).await {
+                            Ok(0) | Err(_) => return,
+                            Ok(len) => request.extend_from_slice(&buf[..len]),
+                        }
+                    }
+                    match response {
+                        Some(response) => {
+                            // One request a connection, so every one is counted
+                            let response =
+                                response.replacen("\r\n", "\r\nConnection: close\r\n", 1);
+                            socket
+                                .write_all(response.as_bytes())
+                                .await
+                                .unwrap_or_default();
+                            socket.shutdown().await.unwrap_or_default();
+                        }
+                        None => tokio::time::sleep(Duration::from_secs(60)).await,
+                    }
+                });
+            }
+        });
+        (url, count)
+    }
+
+    #[tokio::test]
+    async fn retries_server_errors() {
+        let (url, count) = serve(vec![UNAVAILABLE, UNAVAILABLE, OK]).await;
+        let r = send(reqwest::Client::new().get(&url)).await.unwrap();
+        assert_eq!(r.status(), StatusCode::OK);
+        assert_eq!(r.text().await.unwrap(), "ok");
+        assert_eq!(count.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn returns_the_last_error() {
+        let (url, count) = serve(vec![UNAVAILABLE]).await;
+        let r = send(reqwest::Client::new().get(&url)).await.unwrap();
+        assert_eq!(r.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(count.load(Ordering::SeqCst), MAX_RETRIES as usize + 1);
+    }
+
+    #[tokio::test]
+    async fn client_errors_are_not_retried() {
+        let (url, count) = serve(vec![NOT_FOUND, OK]).await;
+        let r = send(reqwest::Client::new().get(&url)).await.unwrap();
+        assert_eq!(r.status(), StatusCode::NOT_FOUND);
+        assert_eq!(count.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn waits_for_retry_after() {
+        let (url, count) = serve(vec![TOO_MANY, OK]).await;
+        let start = Instant::now();
+        let r = send(reqwest::Client::new().get(&url)).await.unwrap();
+        assert_eq!(r.status(), StatusCode::OK);
+        assert!(start.elapsed() >= Duration::from_secs(1));
+        assert_eq!(count.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn retries_timeouts() {
+        let (url, count) = serve(vec![None, OK]).await;
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_millis(200))
+            .build()
+            .unwrap();
+        let r = send(client.get(&url)).await.unwrap();
+        assert_eq!(r.status(), StatusCode::OK);
+        assert_eq!(count.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn gives_up_on_timeouts() {
+        let (url, count) = serve(vec![None]).await;
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_millis(200))
+            .build()
+            .unwrap();
+        let e = send(client.get(&url)).await.unwrap_err();
+        assert!(e.is_timeout());
+        assert_eq!(count.load(Ordering::SeqCst), MAX_RETRIES as usize + 1);
+    }
+
+    #[test]
+    fn backoff_grows() {
+        for attempt in 0..MAX_RETRIES {
+            let delay = backoff(attempt);
+            assert!(delay >= BACKOFF_BASE / 2);
+            assert!(delay <= BACKOFF_BASE * 2u32.pow(attempt));
+        }
+    }
+}
@@ -1,62 +1,44 @@
 use std::cell::RefCell;
-use std::collections::HashMap;
-use std::convert::Infallible;
 use std::fs;
 use std::iter::FromIterator;
-use std::ops::{ControlFlow, FromResidual, Try};
-use std::path::PathBuf;
-use std::process;
+use std::path::{Path, PathBuf};
 use std::sync::Mutex;
 use std::time::{Duration, SystemTime};
 
-use chrono::prelude::*;
-use failure::{self, format_err, Fail};
+use failure::{self, format_err};
+use image::GenericImageView;
 use lazy_static::lazy_static;
 use qmetaobject::{future::execute_async, *};
-use rand::{rngs::SmallRng, Rng, SeedableRng};
 use regex::Regex;
-use reqwest;
-use serde::Deserialize;
-use tokio::{fs::File, io::AsyncWriteExt};
+use serde::{Deserialize, Serialize};
 
 use crate::async_utils::enter_tokio;
-use crate::config::Config;
+use crate::config::{Config, Snapshot};
+use crate::downloader::{self, DownloadError, Progress};
+use crate::error::Error;
+use crate::favorites::Favorite;
 use crate::listmodel::{MutListItem, MutListModel};
+use crate::monitor::{self, Monitor};
+use crate::scale;
+use crate::scheduler;
+use crate::setter::UnknownMonitor;
+use crate::source::{self, ImageMeta, RawImage, SourceKind};
 
 const MAX_WP_NUM_IN_A_PAGE: usize = 20;
 const ORIGINAL_RESOLUTION: &str = "1920x1200";
-#[cfg(not(test))]
-const AVOS_ID: &str = env!("AVOS_ID");
-#[cfg(not(test))]
-const AVOS_KEY: &str = env!("AVOS_KEY");
-#[cfg(test)]
-const AVOS_ID: &str = "";
-#[cfg(test)]
-const AVOS_KEY: &str = "";
+const CURRENT_WP_FILE: &str = "current.json";
 
 lazy_static! {
-    static ref CURRENT_WP: Mutex<Option<String>> = Mutex::new(None);
-    static ref CLIENT: reqwest::Client = {
-        let mut headers = reqwest::header::HeaderMap::new();
-        headers.insert(
-            "X-AVOSCloud-Application-Id",
-            reqwest::header::HeaderValue::from_static(AVOS_ID),
-        );
-        headers.insert(
-            "X-AVOSCloud-Application-Key",
-            reqwest::header::HeaderValue::from_static(AVOS_KEY),
-        );
-        reqwest::Client::builder()
-            .default_headers(headers)
-            .build()
-            .expect("build client")
-    };
+    static ref CURRENT_WP: Mutex<Option<CurrentWallpaper>> = Mutex::new(None);
+    static ref CURRENT_WP_LISTENERS: Mutex<Vec<Box<dyn Fn(&CurrentWallpaper) + Send>>> =
+        Mutex::default();
 }
 
 #[derive(QObject, Default)]
 pub struct Wallpapers {
     base: qt_base_class!(trait QObject),
-    pub error: qt_signal!(err: QString),
+    /// `category` is one of `Error::category`, `retry` is if trying again later may work
+    pub error: qt_signal!(err: QString, category: QString, retry: bool),
     pub list: qt_property!(RefCell<MutListModel<QWallpaper>>; CONST),
     pub favorites: qt_property!(RefCell<MutListModel<QWallpaper>>; CONST),
     pub list_loading: qt_property!(bool; NOTIFY list_loading_changed),
@@ -65,14 +47,20 @@ pub struct Wallpapers {
     pub favorites_loading_changed: qt_signal!(),
     pub fetch_next_page: qt_method!(fn(&self)),
     pub next_page_favorites: qt_method!(fn(&self)),
+    pub reload: qt_method!(fn(&mut self)),
     pub download: qt_method!(fn(&mut self, index: usize, in_favorites_page: bool)),
+    pub cancel_download: qt_method!(fn(&self, index: usize, in_favorites_page: bool)),
     pub like: qt_method!(fn(&mut self, index: usize, in_favorites_page: bool)),
     pub set_wallpaper: qt_method!(fn(&self, index: usize, in_favorites_page: bool)),
     pub next_wallpaper: qt_method!(fn(&self)),
+    pub like_current: qt_method!(fn(&mut self)),
     pub diskusage_others: qt_property!(u64; NOTIFY diskusage_changed),
     pub diskusage_favorites: qt_property!(u64; NOTIFY diskusage_changed),
     pub diskusage_changed: qt_signal!(),
     pub clear_other_wallpapers: qt_method!(fn(&mut self)),
+    pub export_favorites: qt_method!(fn(&self, path: QString, with_images: bool)),
+    pub import_favorites: qt_method!(fn(&mut self, path: QString)),
+    pub set_config: qt_method!(fn(&mut self, key: QString, value: QString)),
     pub config: qt_property!(RefCell<Config>; CONST),
     offset: usize,
     favorites_offset: usize,
@@ -88,72 +76,143 @@ impl Wallpapers {
         s
     }
 
+    /// Show errors of the scheduler while this object is alive, must be pinned
+    pub fn watch_scheduler(&self) {
+        let qptr = QPointer::from(&*self);
+        let report = queued_callback(move |err: Error| {
+            if let Some(this) = qptr.as_ref() {
+                this.report(err);
+            }
+        });
+        scheduler::set_error_reporter(Some(Box::new(report)));
+    }
+
     pub fn fetch_next_page(&mut self) {
         self.list_loading = true;
         self.list_loading_changed();
 
         let offset = self.offset;
         self.offset += MAX_WP_NUM_IN_A_PAGE;
+        let source = self.config.borrow().source();
         let this = QPointer::from(&*self);
         execute_async(enter_tokio(async move {
-            let this = this.as_ref().expect("");
-            match fetch_wallpapers(&CLIENT, offset, MAX_WP_NUM_IN_A_PAGE).await {
+            // The window is closed
+            let this = match this.as_ref() {
+                Some(this) => this,
+                None => return,
+            };
+            match source::list(source, offset, MAX_WP_NUM_IN_A_PAGE).await {
                 Ok(images) => {
                     let mutp = unsafe { &mut *(this as *const _ as *mut Self) };
+                    let offline = source::offline();
+                    if offline && offset == 0 {
+                        let err = "showing cached wallpapers".to_owned();
+                        this.report(Error::Network(err));
+                    }
                     for v in images {
                         let mut wallpaper: QWallpaper = (&v).into();
-                        wallpaper.like = mutp
-                            .config
-                            .borrow()
-                            .likes
-                            .iter()
-                            .any(|x| x == &wallpaper.id);
+                        wallpaper.like = mutp.config.borrow().favorites.contains(&wallpaper.id);
+                        mutp.use_downloaded(&mut wallpaper, offline);
                         mutp.list.borrow_mut().push(wallpaper);
                     }
                     mutp.list_loading = false;
                     mutp.list_loading_changed();
                 }
                 Err(e) => {
-                    this.error(e.to_string().into());
+                    this.report(e.into());
                 }
             }
         }));
     }
 
     pub fn next_page_favorites(&mut self) {
-        if self.favorites.borrow().len() == self.config.borrow().likes.len() {
+        if self.favorites.borrow().len() == self.config.borrow().favorites.len() {
             return;
         }
         self.favorites_loading = true;
         self.favorites_loading_changed();
 
-        let favorites = &self.config.borrow().likes;
-        let mut end = self.favorites_offset + MAX_WP_NUM_IN_A_PAGE;
-        end = std::cmp::min(end, favorites.len());
-        let favorites = favorites[self.favorites_offset..end].to_vec();
-        self.favorites_offset = end;
+        let config = self.config.borrow();
+        let favorites = config
+            .favorites
+            .page(self.favorites_offset, MAX_WP_NUM_IN_A_PAGE);
+        let mut page: Vec<RawImage> = favorites.iter().map(Favorite::to_raw).collect();
+        let incomplete: Vec<String> = favorites
+            .iter()
+            .filter(|f| !f.is_complete())
+            .map(|f| f.id().to_owned())
+            .collect();
+        std::mem::drop(config);
+        self.favorites_offset += page.len();
 
         let this = QPointer::from(&*self);
         execute_async(enter_tokio(async move {
-            let this = this.as_ref().expect("");
-            match fetch_wallpapers_by_id(&CLIENT, &favorites).await {
-                Ok(images) => {
-                    let mutp = unsafe { &mut *(this as *const _ as *mut Self) };
-                    for img in images {
-                        let mut wallpaper: QWallpaper = (&img).into();
-                        wallpaper.like = true;
-                        mutp.favorites.borrow_mut().push(wallpaper);
+            // The window is closed
+            let this = match this.as_ref() {
+                Some(this) => this,
+                None => return,
+            };
+            let mutp = unsafe { &mut *(this as *const _ as *mut Self) };
+            if !incomplete.is_empty() {
+                // Migrated from bare IDs, which are all from LeanCloud or the local folder
+                match source::by_id(SourceKind::LeanCloud, &incomplete).await {
+                    Ok(images) => {
+                        let mut config = mutp.config.borrow_mut();
+                        for img in images {
+                            config.favorites.update(&img);
+                            if let Some(v) = page.iter_mut().find(|v| v.object_id == img.object_id)
+                            {
+                                *v = img;
+                            }
+                        }
+                        if let Err(e) = config.save_favorites() {
+                            this.report(e.into());
+                        }
+                    }
+                    Err(e) => {
+                        this.report(e.into());
                     }
-                    mutp.favorites_loading = false;
-                    mutp.favorites_loading_changed();
-                }
-                Err(e) => {
-                    this.error(e.to_string().into());
                 }
             }
+            let offline = source::offline();
+            for img in page {
+                let mut wallpaper: QWallpaper = (&img).into();
+                wallpaper.like = true;
+                mutp.use_downloaded(&mut wallpaper, offline);
+                mutp.favorites.borrow_mut().push(wallpaper);
+            }
+            mutp.favorites_loading = false;
+            mutp.favorites_loading_changed();
         }));
     }
 
+    /// Show the downloaded file if there is one, also as the preview if it can't be loaded
+    fn use_downloaded(&self, wallpaper: &mut QWallpaper, offline: bool) {
+        let config = self.config.borrow();
+        let file = downloaded_file(
+            &config.download_dir,
+            &config.download_resolution(),
+            &wallpaper.id,
+        );
+        if let Some(file) = file {
+            let url = QString::from("file:".to_owned() + &file.to_string_lossy());
+            if offline {
+                wallpaper.preview = url.clone();
+            }
+            wallpaper.image = url;
+        }
+    }
+
+    /// Start over from the first page, after the source is changed
+    pub fn reload(&mut self) {
+        self.list.borrow_mut().reset_data(Vec::new());
+        self.favorites.borrow_mut().reset_data(Vec::new());
+        self.offset = 0;
+        self.favorites_offset = 0;
+        self.fetch_next_page();
+        self.next_page_favorites();
+    }
+
     pub fn download(&mut self, index: usize, in_favorites_page: bool) {
         let mut list = if in_favorites_page {
             self.favorites.borrow_mut()
@@ -163,10 +222,9 @@ impl Wallpapers {
         let wp = &mut list[index];
         wp.loading = true;
         let id = wp.id.clone();
-        let urlbase = wp.urlbase.clone();
+        let raw = wp.raw.clone();
         let config = self.config.borrow();
-        let resolution =
-            config.resolution.download[config.resolution.download_index].to_qbytearray();
+        let resolution = config.download_resolution();
         let download_dir = config.download_dir.clone();
         let try_original = wp.wp && config.resolution.original;
 
@@ -176,29 +234,66 @@ impl Wallpapers {
         std::mem::drop(list);
         let this = QPointer::from(&*self);
         execute_async(enter_tokio(async move {
-            let resolution = resolution.to_str().unwrap();
-            let this = this.as_ref().expect("");
-            // Safety: is this safe?
-            let this = unsafe { &mut *(this as *const _ as *mut Self) };
-            match download_image(&id, &urlbase, resolution, &download_dir, try_original).await {
-                Ok(path) => {
-                    let mut list = if in_favorites_page {
-                        this.favorites.borrow_mut()
-                    } else {
-                        this.list.borrow_mut()
-                    };
-                    list[index].image = ("file:".to_owned() + &path).into();
-                    list[index].loading = false;
-                    let idx = (&mut *list as &mut dyn QAbstractListModel).row_index(index as i32);
-                    (&mut *list as &mut dyn QAbstractListModel).data_changed(idx, idx);
+            // The window is closed
+            let this = match this.as_ref() {
+                Some(this) => this,
+                None => return,
+            };
+            let on_progress = |progress: Progress| {
+                this.change_item(&id, in_favorites_page, |wp| wp.progress = progress.ratio())
+            };
+            let r = download_image(&raw, &resolution, &download_dir, try_original, on_progress);
+            let r = r.await;
+            this.change_item(&id, in_favorites_page, |wp| {
+                wp.loading = false;
+                wp.progress = 0.0;
+                if let Ok(path) = &r {
+                    wp.image = ("file:".to_owned() + path).into();
                 }
-                Err(e) => {
-                    this.error(e.to_string().into());
+            });
+            if let Err(e) = r {
+                let cancelled = matches!(
+                    e.downcast_ref::<DownloadError>(),
+                    Some(DownloadError::Cancelled)
+                );
+                if !cancelled {
+                    this.report(e.into());
                 }
             }
         }));
     }
 
+    pub fn cancel_download(&self, index: usize, in_favorites_page: bool) {
+        let list = if in_favorites_page {
+            self.favorites.borrow()
+        } else {
+            self.list.borrow()
+        };
+        let wp = &list[index];
+        let config = self.config.borrow();
+        let try_original = wp.wp && config.resolution.original;
+        cancel_download(
+            &wp.raw,
+            &config.download_resolution(),
+            &config.download_dir,
+            try_original,
+        );
+    }
+
+    /// Change the wallpaper of `id` in the list, if it's still there
+    fn change_item(&self, id: &str, in_favorites_page: bool, f: impl FnOnce(&mut QWallpaper)) {
+        let mut list = if in_favorites_page {
+            self.favorites.borrow_mut()
+        } else {
+            self.list.borrow_mut()
+        };
+        if let Some(index) = linear_search_by(&list, |v| v.id == id) {
+            f(&mut list[index]);
+            let idx = (&mut *list as &mut dyn QAbstractListModel).row_index(index as i32);
+            (&mut *list as &mut dyn QAbstractListModel).data_changed(idx, idx);
+        }
+    }
+
     pub fn set_wallpaper(&self, index: usize, in_favorites_page: bool) {
         let wallpaper = &if in_favorites_page {
             self.favorites.borrow()
@@ -207,32 +302,33 @@ impl Wallpapers {
         }[index];
 
         let config = self.config.borrow();
-        let resolution =
-            config.resolution.download[config.resolution.download_index].to_qbytearray();
-        let id = wallpaper.id.clone();
-        let urlbase = wallpaper.urlbase.clone();
+        let resolution = config.download_resolution();
+        let raw = wallpaper.raw.clone();
         let download_dir = config.download_dir.clone();
         let try_original = wallpaper.wp && config.resolution.original;
 
         let this = QPointer::from(&*self);
         execute_async(enter_tokio(async move {
-            let resolution = resolution.to_str().unwrap();
-            let this = this.as_ref().expect("");
-            let file = download_image(&id, &urlbase, resolution, &download_dir, try_original).await;
-
-            match file {
-                Ok(file) => set_wallpaper(&this.config.borrow(), id, &file),
-                Err(e) => {
-                    this.error(e.to_string().into());
-                    return;
-                }
+            // The window is closed
+            let this = match this.as_ref() {
+                Some(this) => this,
+                None => return,
+            };
+            let r: Result<(), failure::Error> = try {
+                let file = download_image(&raw, &resolution, &download_dir, try_original, |_| ());
+                let file = file.await?;
+                let snapshot = this.config.borrow().snapshot();
+                set_wallpaper(&snapshot, &raw, &file).await?;
             };
+            if let Err(e) = r {
+                this.report(e.into());
+            }
         }));
     }
 
     pub fn like(&mut self, index: usize, in_favorites_page: bool) {
         // NOTE: `self.favorites` is favorites in favorites page, not all favorites
-        // `self.config.likes` is the full list
+        // `self.config.favorites` is the full list
 
         let id: String;
         let main_index: Option<usize>;
@@ -272,13 +368,44 @@ impl Wallpapers {
         }
 
         if !favorited {
-            self.config.borrow_mut().likes.insert(0, id);
+            // Not favorited, so it's in the main page
+            let list = self.list.borrow();
+            self.config.borrow_mut().favorites.insert(&list[index].raw);
         } else {
-            let likes = &mut self.config.borrow_mut().likes;
-            let pos = likes.iter().position(|x| *x == *id).expect("unreachable");
-            likes.remove(pos);
+            self.config.borrow_mut().favorites.remove(&id);
+        }
+        if let Err(e) = self.config.borrow().save() {
+            self.report(e.into());
+        }
+        scheduler::update(&self.config.borrow());
+
+        self.update_diskusage_and_autoclean().unwrap_or_default();
+    }
+
+    pub fn like_current(&mut self) {
+        let current = match current_wallpaper(&self.config.borrow().cache_dir) {
+            Some(current) => current,
+            None => return,
+        };
+        let id = current.id.clone();
+        let main_index = linear_search_by(&self.list.borrow(), |v| v.id == id);
+        if let Some(index) = main_index {
+            return self.like(index, false);
+        }
+        let favorites_index = linear_search_by(&self.favorites.borrow(), |v| v.id == id);
+        if let Some(index) = favorites_index {
+            return self.like(index, true);
         }
-        self.config.borrow().save().expect("Failed to save config!");
+
+        // Not in any page
+        if self.config.borrow_mut().favorites.toggle(&current.to_raw()) {
+            // Skip it in favorites page, it has been inserted before `favorites_offset`
+            self.favorites_offset += 1;
+        }
+        if let Err(e) = self.config.borrow().save() {
+            self.report(e.into());
+        }
+        scheduler::update(&self.config.borrow());
 
         self.update_diskusage_and_autoclean().unwrap_or_default();
     }
@@ -295,12 +422,9 @@ impl Wallpapers {
                     fs::remove_dir_all(path)?;
                     continue;
                 }
-                let name = entry
-                    .file_name()
-                    .into_string()
-                    .expect("file name to String");
-                let id = name.split('_').next().expect("Illegal file name");
-                if !config.likes.iter().any(|x| x == id) {
+                let name = entry.file_name();
+                let id = name.to_str().and_then(|name| name.split('_').next());
+                if !id.map_or(false, |id| config.favorites.contains(id)) {
                     fs::remove_file(path)?;
                 }
             }
@@ -309,58 +433,149 @@ impl Wallpapers {
             self.diskusage_changed();
         };
         if let Err(e) = r {
-            self.error(e.to_string().into());
+            self.report(e.into());
+        }
+    }
+
+    pub fn export_favorites(&self, path: QString, with_images: bool) {
+        let config = self.config.borrow();
+        let path = PathBuf::from(String::from_utf16_lossy(path.to_slice()));
+        let download_dir = if with_images {
+            Some(&*config.download_dir)
+        } else {
+            None
+        };
+        if let Err(e) = config.favorites.export(&path, download_dir) {
+            self.report(e.into());
+        }
+    }
+
+    pub fn import_favorites(&mut self, path: QString) {
+        let r: Result<(), failure::Error> = try {
+            let mut config = self.config.borrow_mut();
+            let config = &mut *config;
+            let path = PathBuf::from(String::from_utf16_lossy(path.to_slice()));
+            config.favorites.import(&path, &config.download_dir)?;
+            config.save_favorites()?;
+        };
+        if let Err(e) = r {
+            self.report(e.into());
+            return;
+        }
+        scheduler::update(&self.config.borrow());
+
+        let mut list = self.list.borrow_mut();
+        for i in 0..list.len() {
+            if !list[i].like && self.config.borrow().favorites.contains(&list[i].id) {
+                let mut wallpaper = list[i].clone();
+                wallpaper.like = true;
+                list.change_line(i, wallpaper);
+            }
+        }
+        std::mem::drop(list);
+        self.favorites.borrow_mut().reset_data(Vec::new());
+        self.favorites_offset = 0;
+        self.next_page_favorites();
+        self.update_diskusage_and_autoclean().unwrap_or_default();
+    }
+
+    /// From `biying config set`, see `Config::with_value`
+    pub fn set_config(&mut self, key: QString, value: QString) {
+        let key = String::from_utf16_lossy(key.to_slice());
+        let value = String::from_utf16_lossy(value.to_slice());
+        let r = self.config.borrow().with_value(&key, &value);
+        match r {
+            Ok(config) => self.config.borrow_mut().replace(config),
+            Err(e) => {
+                self.report(e.into());
+                return;
+            }
+        }
+        if let Err(e) = self.config.borrow().save() {
+            self.report(e.into());
+        }
+        if ["source", "local_dir", "network"]
+            .iter()
+            .any(|k| key.split('.').next() == Some(*k))
+        {
+            self.reload();
         }
     }
 
     pub fn next_wallpaper(&self) {
         let this = QPointer::from(&*self);
         execute_async(enter_tokio(async move {
-            let this = this.as_ref().expect("");
-            if let Err(e) = next_wallpaper(&this.config.borrow()).await {
-                this.error(e.to_string().into());
+            // The window is closed
+            let this = match this.as_ref() {
+                Some(this) => this,
+                None => return,
+            };
+            let snapshot = this.config.borrow().snapshot();
+            if let Err(e) = next_wallpaper(&snapshot).await {
+                this.report(e.into());
             }
         }));
     }
 
+    fn report(&self, err: Error) {
+        let category = err.category().into();
+        self.error(err.to_string().into(), category, err.retryable());
+    }
+
     fn update_diskusage_and_autoclean(&mut self) -> Result<(), failure::Error> {
         let config = self.config.borrow();
         let download_dir = fs::read_dir(&config.download_dir)?;
         let mut favorites = 0;
         let mut others = 0;
+        // Detected ones and portrait ones too
+        let kept_resolutions = config.kept_resolutions();
         for entry in download_dir {
             let entry = entry?;
-            let metadata = entry.metadata().expect("Wallpaper file metadata");
+            let metadata = entry.metadata()?;
             if metadata.is_dir() {
                 // remove all other files
                 fs::remove_dir_all(entry.path())?;
                 continue;
             }
 
-            let created = metadata.modified().expect("read metadata created");
-            let outdated = SystemTime::now().duration_since(created)?
+            let created = metadata.modified()?;
+            // Modified in the future counts as just now
+            let outdated = SystemTime::now()
+                .duration_since(created)
+                .unwrap_or_default()
                 > Duration::from_secs(config.autoremove * 24 * 60 * 60);
 
-            let name = entry
-                .file_name()
-                .into_string()
-                .expect("file name to String");
-            if let Some((id, res)) = parse_wallpaper_filename(&name) {
-                let favorited = config.likes.iter().any(|x| x == id);
-                let resolution =
-                    config.resolution.download[config.resolution.download_index].to_qbytearray();
-                let resolution = resolution.to_str().unwrap();
+            // Not ours, we only name files with UTF-8
+            let name = match entry.file_name().into_string() {
+                Ok(name) => name,
+                Err(_) => continue,
+            };
+            if scale::is_scaled(&name) {
+                // Made again when needed
+                let id = name.split('_').next().unwrap_or_default();
+                if outdated {
+                    fs::remove_file(entry.path())?;
+                } else if config.favorites.contains(id) {
+                    favorites += metadata.len();
+                } else {
+                    others += metadata.len();
+                }
+            } else if let Some((id, res)) = parse_wallpaper_filename(&name) {
+                let favorited = config.favorites.contains(id);
                 let file_size = metadata.len();
-                // TODO
-                let valid_resolution =
-                    (res == ORIGINAL_RESOLUTION && config.resolution.original) || res == resolution;
-                if !valid_resolution || (outdated && !favorited) {
+                let valid_resolution = (res == ORIGINAL_RESOLUTION && config.resolution.original)
+                    || kept_resolutions.iter().any(|r| r == res);
+                // Favorites are never removed
+                if !favorited && (!valid_resolution || outdated) {
                     fs::remove_file(entry.path())?;
                 } else if favorited {
                     favorites += file_size;
                 } else {
                     others += file_size;
                 }
+            } else if name.ends_with(downloader::PARTIAL_SUFFIX) && !outdated {
+                // Resumed on next download
+                others += metadata.len();
             } else {
                 // remove all other files
                 fs::remove_file(entry.path())?;
@@ -376,7 +591,7 @@ impl Wallpapers {
 fn parse_wallpaper_filename(file: &str) -> Option<(&str, &str)> {
     lazy_static! {
         static ref WALLPAPER_FILE_NAME: Regex =
-            Regex::new(r#"([[:alnum:]]+)_(\d+x\d+)\.\w+"#).unwrap();
+            Regex::new(r#"([[:alnum:]]+)_(\d+x\d+)\.\w+$"#).unwrap();
     }
     let re = WALLPAPER_FILE_NAME.captures(file)?;
     let id = re.get(1)?;
@@ -384,73 +599,6 @@ fn parse_wallpaper_filename(file: &str) -> Option<(&str, &str)> {
     Some((id.as_str(), res.as_str()))
 }
 
-#[derive(Deserialize)]
-#[serde(untagged)]
-enum Response<T> {
-    Ok { results: Vec<T> },
-    Err { code: i32, error: String },
-}
-
-impl<T> Try for Response<T> {
-    type Output = Vec<T>;
-    type Residual = Result<Infallible, ServerError>;
-    fn branch(self) -> ControlFlow<Self::Residual, <Response<T> as Try>::Output> {
-        match self {
-            Response::Ok { results } => ControlFlow::Continue(results),
-            Response::Err { code, error } => ControlFlow::Break(Err(ServerError { code, error })),
-        }
-    }
-    fn from_output(v: <Response<T> as Try>::Output) -> Self {
-        Response::Ok { results: v }
-    }
-}
-
-impl<T> FromResidual for Response<T> {
-    fn from_residual(residual: <Self as Try>::Residual) -> Self {
-        match residual {
-            Ok(_) => unreachable!(),
-            Err(e) => Self::Err {
-                code: e.code,
-                error: e.error,
-            },
-        }
-    }
-}
-
-#[derive(Fail, Debug)]
-#[fail(display = "Server Error: {}", error)]
-struct ServerError {
-    code: i32,
-    error: String,
-}
-
-#[derive(Deserialize, Clone, Default, Debug)]
-#[serde(rename_all = "camelCase")]
-struct ImageMeta {
-    info: String,
-    market: String,
-    image: ImagePointer,
-}
-
-#[derive(Deserialize, Clone, Default, Debug)]
-#[serde(rename_all = "camelCase")]
-struct ImagePointer {
-    object_id: String,
-}
-
-#[derive(Deserialize, Clone, Default, Debug)]
-#[serde(rename_all = "camelCase")]
-struct RawImage {
-    name: String,
-    urlbase: String,
-    copyright: String,
-    object_id: String,
-    wp: bool,
-    created_at: Option<DateTime<Utc>>,
-    #[serde(skip)]
-    metas: Vec<ImageMeta>,
-}
-
 #[derive(Default, Clone)]
 pub struct QWallpaper {
     pub name: qt_property!(QString),
@@ -461,8 +609,10 @@ pub struct QWallpaper {
     pub like: qt_property!(bool),
     pub image: qt_property!(QString),
     pub loading: qt_property!(bool),
+    /// From 0 to 1 while loading
+    pub progress: qt_property!(f64),
     id: String,
-    urlbase: String,
+    raw: RawImage,
 }
 
 impl MutListItem for QWallpaper {
@@ -476,6 +626,7 @@ impl MutListItem for QWallpaper {
             5 => QMetaType::to_qvariant(&self.like),
             6 => QMetaType::to_qvariant(&self.image),
             7 => QMetaType::to_qvariant(&self.loading),
+            8 => QMetaType::to_qvariant(&self.progress),
             _ => QVariant::default(),
         }
     }
@@ -489,6 +640,7 @@ impl MutListItem for QWallpaper {
             5 => <_>::from_qvariant(value.clone()).map(|v| self.like = v),
             6 => <_>::from_qvariant(value.clone()).map(|v| self.image = v),
             7 => <_>::from_qvariant(value.clone()).map(|v| self.loading = v),
+            8 => <_>::from_qvariant(value.clone()).map(|v| self.progress = v),
             _ => None,
         }
         .is_some()
@@ -503,53 +655,99 @@ impl MutListItem for QWallpaper {
             QByteArray::from("like"),
             QByteArray::from("image"),
             QByteArray::from("loading"),
+            QByteArray::from("progress"),
         ]
     }
 }
 
-async fn download_image(
-    id: &str,
-    urlbase: &str,
+/// Files `wallpaper` may be downloaded to, with their resolutions, in the order they are tried
+fn image_files(
+    wallpaper: &RawImage,
     resolution: &str,
-    output_dir: &PathBuf,
+    output_dir: &Path,
     try_original: bool,
-) -> Result<String, failure::Error> {
+) -> Vec<(String, PathBuf)> {
     let mut resolutions: &[&str] = &[resolution];
     if try_original && resolution == "1920x1080" {
         resolutions = &["1920x1200", "1920x1080"];
     }
-    for resolution in resolutions {
-        let output = output_dir.join(format!("{}_{}.jpg", id, resolution));
+    resolutions
+        .iter()
+        .map(|res| {
+            let file = format!("{}_{}.jpg", wallpaper.object_id, res);
+            (res.to_string(), output_dir.join(file))
+        })
+        .collect()
+}
+
+pub async fn download_image(
+    wallpaper: &RawImage,
+    resolution: &str,
+    output_dir: &PathBuf,
+    try_original: bool,
+    mut on_progress: impl FnMut(Progress),
+) -> Result<String, failure::Error> {
+    if let Some(file) = wallpaper.source().local_file(&wallpaper.urlbase) {
+        return Ok(file.to_string_lossy().into());
+    }
+    for (resolution, output) in image_files(wallpaper, resolution, output_dir, try_original) {
         if !output.exists() {
-            let mut r = reqwest::get(&format!(
-                "https://wpdn.bohan.co{}_{}.jpg",
-                urlbase, resolution
-            ))
-            .await?;
-            if r.status() == reqwest::StatusCode::NOT_FOUND {
-                continue;
-            }
-            if !r.status().is_success() {
-                return Err(format_err!("Server Error: {}", r.status()));
-            }
+            let url = wallpaper
+                .source()
+                .image_url(&wallpaper.urlbase, &resolution);
             if !output_dir.exists() {
                 fs::create_dir_all(&output_dir)?;
             }
-            let mut file = File::create(&output).await?;
-            while let Some(chunk) = r.chunk().await? {
-                file.write(&chunk).await?;
+            let check = move |path: &Path| check_image(path, &resolution);
+            let mut download = downloader::download(&url, &output, check);
+            while let Some(progress) = download.progress().await {
+                on_progress(progress);
+            }
+            if !download.finish().await? {
+                continue;
             }
         }
         return Ok(output.to_string_lossy().into());
     }
-    unreachable!()
+    let msg = format!("{} is not found in any resolution", wallpaper.object_id);
+    Err(Error::Server(msg).into())
+}
+
+/// Stop downloading `wallpaper`, see `download_image`
+pub fn cancel_download(
+    wallpaper: &RawImage,
+    resolution: &str,
+    output_dir: &Path,
+    try_original: bool,
+) -> bool {
+    image_files(wallpaper, resolution, output_dir, try_original)
+        .iter()
+        .any(|(_, output)| downloader::cancel(output))
+}
+
+/// Decode the whole image, so error pages and truncated files are caught,
+/// and make sure it's in `resolution`
+pub fn check_image(path: &Path, resolution: &str) -> Result<(), failure::Error> {
+    let img = image::io::Reader::open(path)?
+        .with_guessed_format()?
+        .decode()?;
+    let (width, height) = img.dimensions();
+    if format!("{}x{}", width, height) != resolution {
+        return Err(format_err!(
+            "expected {}, got {}x{}",
+            resolution,
+            width,
+            height
+        ));
+    }
+    Ok(())
 }
 
 impl From<&RawImage> for QWallpaper {
     fn from(v: &RawImage) -> QWallpaper {
         QWallpaper {
             name: v.name.as_str().into(),
-            preview: format!("https://wpdn.bohan.co{}_800x480.jpg", v.urlbase).into(),
+            preview: v.source().image_url(&v.urlbase, "800x480").into(),
             copyright: v.copyright.as_str().into(),
             metas: QVariantList::from_iter(
                 v.metas
@@ -559,7 +757,7 @@ impl From<&RawImage> for QWallpaper {
             ),
             wp: v.wp,
             id: v.object_id.clone(),
-            urlbase: v.urlbase.clone(),
+            raw: v.clone(),
             ..QWallpaper::default()
         }
     }
@@ -580,176 +778,320 @@ impl From<&ImageMeta> for QWallpaperInfo {
     }
 }
 
-async fn fetch_wallpapers(
-    client: &reqwest::Client,
-    offset: usize,
-    limit: usize,
-) -> Result<Vec<RawImage>, failure::Error> {
-    let offset = offset.to_string();
-    let limit = limit.to_string();
-    let url = reqwest::Url::parse_with_params(
-        "https://leanapi.bohan.co/1.1/classes/Image",
-        &[
-            ("order", "-createdAt"),
-            ("skip", &offset),
-            ("limit", &limit),
-        ],
-    )
-    .expect("parse url");
-
-    let resp: Response<RawImage> = client.get(url).send().await?.json().await?;
-    let images = resp?;
+pub async fn next_wallpaper(config: &Snapshot) -> Result<(), failure::Error> {
+    let per_monitor = config.auto_change.per_monitor && config.setter.supports_monitors();
+    // Detected every time, so changes of the layout are followed
+    let monitors = if per_monitor || config.auto_resolution {
+        monitor::detect().await
+    } else {
+        Vec::new()
+    };
+    if !per_monitor || monitors.len() < 2 {
+        let resolution = resolution_for(config, monitor::largest(&monitors));
+        let (wallpaper, path) = prepare_wallpaper(config, 0, &resolution).await?;
+        return set_wallpaper(config, &wallpaper, &path).await;
+    }
 
-    fill_wallpapers_metadata(client, images).await
+    let mut wallpapers = Vec::new();
+    for (nth, monitor) in monitors.iter().enumerate() {
+        let resolution = resolution_for(config, Some(monitor));
+        wallpapers.push(prepare_wallpaper(config, nth, &resolution).await?);
+    }
+    let paths: Vec<&str> = wallpapers.iter().map(|(_, path)| &**path).collect();
+    apply_per_monitor(config, &monitors, &paths).await?;
+    // The one on the primary monitor
+    let (wallpaper, path) = &wallpapers[0];
+    record_current(config, wallpaper, path);
+    Ok(())
 }
 
-async fn fetch_wallpapers_by_id<'a, T: AsRef<str>>(
-    client: &reqwest::Client,
-    id_list: &[T],
-) -> Result<Vec<RawImage>, failure::Error> {
-    let where_query: Vec<String> = id_list
-        .iter()
-        .map(|img| format!(r#"{{"objectId":"{}"}}"#, img.as_ref()))
-        .collect();
-    let where_query = format!("{{\"$or\":[{}]}}", where_query.join(","));
-
-    let url = reqwest::Url::parse_with_params(
-        "https://leanapi.bohan.co/1.1/classes/Image",
-        &[("where", &where_query)],
-    )
-    .expect("parse url");
-
-    let resp: Response<RawImage> = client.get(url).send().await?.json().await?;
-    let mut images = resp?;
-    // Keep the order
-    let id_index: HashMap<&str, usize> = id_list
-        .iter()
-        .enumerate()
-        .map(|(index, id)| (id.as_ref(), index))
-        .collect();
-    images.sort_by_key(|img| id_index.get(&*img.object_id));
-
-    fill_wallpapers_metadata(client, images).await
+/// The resolution to download for `monitor`, detected or chosen in settings
+fn resolution_for(config: &Snapshot, monitor: Option<&Monitor>) -> String {
+    let monitor = match monitor {
+        Some(monitor) => monitor,
+        None => return config.resolution.clone(),
+    };
+    let detected = if config.auto_resolution {
+        monitor::best_resolution(&config.resolutions, monitor.width, monitor.height)
+    } else {
+        None
+    };
+    detected.unwrap_or_else(|| {
+        if monitor.is_portrait() {
+            config.portrait_resolution.clone()
+        } else {
+            config.resolution.clone()
+        }
+    })
 }
 
-async fn fill_wallpapers_metadata(
-    client: &reqwest::Client,
-    mut images: Vec<RawImage>,
-) -> Result<Vec<RawImage>, failure::Error> {
-    let where_query: Vec<String> = images
-        .iter()
-        .map(|img| {
-            format!(
-                r#"{{"image":{{"__type":"Pointer","className":"Image","objectId":"{}"}}}}"#,
-                img.object_id
-            )
-        })
-        .collect();
-    let where_query = format!("{{\"$or\":[{}]}}", where_query.join(","));
-
-    let url = reqwest::Url::parse_with_params(
-        "https://leanapi.bohan.co/1.1/classes/Archive",
-        // Default limit is 100, maximum is 1000
-        &[("where", &*where_query), ("limit", "1000")],
-    )
-    .expect("parse url");
-
-    let resp: Response<ImageMeta> = client.get(url).send().await?.json().await?;
-    let metas = resp?;
-
-    for meta in metas {
-        for img in &mut images {
-            if meta.image.object_id == img.object_id {
-                img.metas.push(meta);
-                break;
-            }
+/// Choose the `nth` wallpaper and download it, or a downloaded one if offline
+async fn prepare_wallpaper(
+    config: &Snapshot,
+    nth: usize,
+    resolution: &str,
+) -> Result<(RawImage, String), failure::Error> {
+    let r: Result<_, failure::Error> = try {
+        let wallpaper = choose_wallpaper(config, nth).await?;
+        let path = download_image(
+            &wallpaper,
+            resolution,
+            &config.download_dir,
+            wallpaper.wp && config.original,
+            |_| (),
+        )
+        .await?;
+        (wallpaper, path)
+    };
+    match r {
+        Ok(v) => Ok(v),
+        // Offline, choose from the downloaded ones instead
+        Err(e) if Error::from(&e).is_network() => {
+            Ok(downloaded_wallpaper(config, resolution).ok_or(e)?)
         }
+        Err(e) => Err(e),
     }
-
-    Ok(images)
 }
 
-// TODO: allow systray run this
-async fn next_wallpaper(config: &Config) -> Result<(), failure::Error> {
-    let resolution = config.resolution.download[config.resolution.download_index].to_qbytearray();
-    let resolution = resolution.to_str().unwrap();
+/// `nth` is only used in Newest mode, others are random anyway
+async fn choose_wallpaper(config: &Snapshot, nth: usize) -> Result<RawImage, failure::Error> {
     let wallpaper = match config.auto_change.mode {
         // Newest
-        0 => fetch_wallpapers(&CLIENT, 0, 1).await?.pop().expect(""),
+        0 => source::list(config.source, nth, 1)
+            .await?
+            .pop()
+            .ok_or_else(|| format_err!("No wallpapers to choose from"))?,
         // Favorites
         1 => {
-            let idx = rand::random::<usize>() % config.likes.len();
-            let id = &config.likes[idx];
-            fetch_wallpapers_by_id(&CLIENT, &[id])
-                .await?
-                .pop()
-                .expect("")
+            if config.favorites.is_empty() {
+                return Err(format_err!("No favorites to choose from"));
+            }
+            let idx = rand::random::<usize>() % config.favorites.len();
+            let favorite = &config.favorites.page(idx, 1)[0];
+            if favorite.is_complete() {
+                favorite.to_raw()
+            } else {
+                let id = favorite.id();
+                source::by_id(SourceKind::LeanCloud, &[id.to_owned()])
+                    .await?
+                    .pop()
+                    .ok_or_else(|| format_err!("Wallpaper {} not found", id))?
+            }
         }
         // Random
-        2 => random_wallpaper(&CLIENT).await?,
-        _ => unreachable!(),
+        2 => source::random(config.source).await?,
+        mode => return Err(format_err!("Unknown auto change mode: {}", mode)),
+    };
+    Ok(wallpaper)
+}
+
+/// A random downloaded wallpaper of the mode, or the newest one in Newest mode
+fn downloaded_wallpaper(config: &Snapshot, resolution: &str) -> Option<(RawImage, String)> {
+    let mut candidates: Vec<(RawImage, String)> = fs::read_dir(&config.download_dir)
+        .ok()?
+        .filter_map(|entry| {
+            let path = entry.ok()?.path();
+            let (id, res) = parse_wallpaper_filename(path.file_name()?.to_str()?)?;
+            if res != resolution && res != ORIGINAL_RESOLUTION {
+                return None;
+            }
+            let favorite = config.favorites.get(id);
+            if config.auto_change.mode == 1 && favorite.is_none() {
+                return None;
+            }
+            let wallpaper = favorite
+                .filter(|f| f.is_complete())
+                .map(Favorite::to_raw)
+                .or_else(|| source::cached(id))
+                .unwrap_or_else(|| RawImage {
+                    name: id.to_owned(),
+                    object_id: id.to_owned(),
+                    ..RawImage::default()
+                });
+            Some((wallpaper, path.to_string_lossy().into_owned()))
+        })
+        .collect();
+    if candidates.is_empty() {
+        return None;
+    }
+    let idx = if config.auto_change.mode == 0 {
+        (0..candidates.len()).max_by_key(|&i| candidates[i].0.created_at)?
+    } else {
+        rand::random::<usize>() % candidates.len()
+    };
+    Some(candidates.swap_remove(idx))
+}
+
+fn downloaded_file(download_dir: &Path, resolution: &str, id: &str) -> Option<PathBuf> {
+    [resolution, ORIGINAL_RESOLUTION]
+        .iter()
+        .map(|res| download_dir.join(format!("{}_{}.jpg", id, res)))
+        .find(|path| path.exists())
+}
+
+pub async fn set_wallpaper_by_id(config: &Snapshot, id: &str) -> Result<(), failure::Error> {
+    let favorite = config.favorites.get(id).filter(|f| f.is_complete());
+    let wallpaper = match favorite {
+        Some(favorite) => favorite.to_raw(),
+        None => source::by_id(config.source, &[id.to_owned()])
+            .await?
+            .pop()
+            .ok_or_else(|| format_err!("Wallpaper {} not found", id))?,
     };
     let path = download_image(
-        &wallpaper.object_id,
-        &wallpaper.urlbase,
-        resolution,
+        &wallpaper,
+        &config.resolution,
         &config.download_dir,
-        wallpaper.wp && config.resolution.original,
+        wallpaper.wp && config.original,
+        |_| (),
     )
     .await?;
-    set_wallpaper(&config, wallpaper.object_id, &path);
-    Ok(())
+    set_wallpaper(config, &wallpaper, &path).await
 }
 
-async fn random_wallpaper(client: &reqwest::Client) -> Result<RawImage, failure::Error> {
-    static mut WP_COUNT: Option<usize> = None;
-    thread_local! {
-        static SMALL_RNG: RefCell<SmallRng> = RefCell::new(SmallRng::from_entropy());
+/// Set it by the setter of the desktop enviroment, it's the current wallpaper only if that succeeds.
+/// Setters supporting monitors set every monitor
+pub async fn set_wallpaper(
+    config: &Snapshot,
+    wallpaper: &RawImage,
+    file: &str,
+) -> Result<(), failure::Error> {
+    let per_monitor = config.setter.supports_monitors();
+    let monitors = if per_monitor || scale::Mode::from_index(config.scale).is_some() {
+        monitor::detect().await
+    } else {
+        Vec::new()
+    };
+    if !per_monitor || monitors.is_empty() {
+        let file = screen_sized(config, file, monitor::largest(&monitors)).await?;
+        config.setter.apply(Path::new(&file), None).await?;
+    } else {
+        apply_per_monitor(config, &monitors, &vec![file; monitors.len()]).await?;
     }
+    record_current(config, wallpaper, file);
+    Ok(())
+}
 
-    let wp_count = unsafe {
-        if let Some(count) = WP_COUNT {
-            count
-        } else {
-            let url = reqwest::Url::parse_with_params(
-                "https://leanapi.bohan.co/1.1/classes/Image",
-                &[("count", "1")],
-            )
-            .expect("parse url");
-            #[derive(Deserialize)]
-            struct Resp {
-                count: usize,
+/// Set `files[i]` on `monitors[i]`, or the first one on all monitors
+/// if the desktop names them differently
+async fn apply_per_monitor(
+    config: &Snapshot,
+    monitors: &[Monitor],
+    files: &[&str],
+) -> Result<(), failure::Error> {
+    for (monitor, file) in monitors.iter().zip(files) {
+        let sized = screen_sized(config, file, Some(monitor)).await?;
+        let r = config
+            .setter
+            .apply(Path::new(&sized), Some(&monitor.name))
+            .await;
+        match r {
+            Err(e) if e.downcast_ref::<UnknownMonitor>().is_some() => {
+                let file = screen_sized(config, files[0], monitor::largest(monitors)).await?;
+                return config.setter.apply(Path::new(&file), None).await;
             }
-            let resp: Resp = client.get(url).send().await?.json().await?;
-            WP_COUNT = Some(resp.count);
-            resp.count
+            r => r?,
         }
+    }
+    Ok(())
+}
+
+/// `file` scaled to the size of `monitor`, if it's enabled and the monitor is found
+async fn screen_sized(
+    config: &Snapshot,
+    file: &str,
+    monitor: Option<&Monitor>,
+) -> Result<String, failure::Error> {
+    let (mode, monitor) = match (scale::Mode::from_index(config.scale), monitor) {
+        (Some(mode), Some(monitor)) => (mode, monitor),
+        _ => return Ok(file.to_owned()),
+    };
+    let path = scale::scaled(
+        Path::new(file),
+        &config.download_dir,
+        mode,
+        monitor.width,
+        monitor.height,
+    )
+    .await?;
+    Ok(path.to_string_lossy().into_owned())
+}
+
+fn record_current(config: &Snapshot, wallpaper: &RawImage, file: &str) {
+    let current = CurrentWallpaper {
+        id: wallpaper.object_id.clone(),
+        path: file.to_owned(),
+        name: wallpaper.name.clone(),
+        copyright: wallpaper.copyright.clone(),
+        metas: wallpaper.metas.clone(),
+        urlbase: wallpaper.urlbase.clone(),
+        source: wallpaper.source,
+        wp: wallpaper.wp,
     };
+    // Also remember it on disk, for other processes
+    let json = serde_json::to_vec(&current).expect("serialize current wallpaper");
+    fs::write(config.cache_dir.join(CURRENT_WP_FILE), json).unwrap_or_default();
+    for listener in &*CURRENT_WP_LISTENERS.lock().unwrap() {
+        (listener)(&current);
+    }
+    *CURRENT_WP.lock().unwrap() = Some(current);
+}
 
-    let n = SMALL_RNG.with(|rng| rng.borrow_mut().gen::<usize>()) % wp_count;
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct CurrentWallpaper {
+    pub id: String,
+    pub path: String,
+    #[serde(default)]
+    pub name: String,
+    #[serde(default)]
+    pub copyright: String,
+    #[serde(default)]
+    pub metas: Vec<ImageMeta>,
+    #[serde(default)]
+    pub urlbase: String,
+    #[serde(default)]
+    pub source: SourceKind,
+    #[serde(default)]
+    pub wp: bool,
+}
 
-    let url = reqwest::Url::parse_with_params(
-        "https://leanapi.bohan.co/1.1/classes/Image",
-        &[("limit", "1"), ("skip", &n.to_string())],
-    )
-    .expect("parse url");
+impl CurrentWallpaper {
+    pub fn to_raw(&self) -> RawImage {
+        RawImage {
+            name: self.name.clone(),
+            urlbase: self.urlbase.clone(),
+            copyright: self.copyright.clone(),
+            object_id: self.id.clone(),
+            wp: self.wp,
+            metas: self.metas.clone(),
+            source: self.source,
+            ..RawImage::default()
+        }
+    }
+}
 
-    let resp: Response<RawImage> = client.get(url).send().await?.json().await?;
-    let mut images = resp?;
+/// The wallpaper last set, by this process or others
+pub fn current_wallpaper(cache_dir: &Path) -> Option<CurrentWallpaper> {
+    if let Some(current) = &*CURRENT_WP.lock().unwrap() {
+        return Some(current.clone());
+    }
+    let json = fs::read(cache_dir.join(CURRENT_WP_FILE)).ok()?;
+    serde_json::from_slice(&json).ok()
+}
 
-    Ok(images.pop().expect(""))
+/// Call `f` every time the wallpaper is changed by this process
+pub fn on_wallpaper_changed(f: impl Fn(&CurrentWallpaper) + Send + 'static) {
+    CURRENT_WP_LISTENERS.lock().unwrap().push(Box::new(f));
 }
 
-fn set_wallpaper(config: &Config, id: String, file: &str) {
-    let de = &config.de.borrow()[config.de_index];
-    let cmd = String::from_utf16_lossy(de.cmd.to_slice());
-    process::Command::new("sh")
-        .env("WALLPAPER", file)
-        .arg("-c")
-        .arg(&cmd)
-        .spawn()
-        .expect("");
-    *CURRENT_WP.lock().unwrap() = Some(id);
+/// Add the current wallpaper to favorites, or remove it.
+/// Returns its ID and `true` if it's favorited now
+pub fn toggle_like_current(config: &mut Config) -> Result<(String, bool), failure::Error> {
+    let current = current_wallpaper(&config.cache_dir)
+        .ok_or_else(|| format_err!("No wallpaper has been set yet"))?;
+    let liked = config.favorites.toggle(&current.to_raw());
+    config.save_favorites()?;
+    Ok((current.id, liked))
 }
 
 fn linear_search_by<T>(s: &[T], f: impl Fn(&T) -> bool) -> Option<usize> {
@@ -0,0 +1,125 @@
+//! Only one instance runs at a time, later ones forward their commands to it
+//! through a unix socket, one command per line
+
+use std::env;
+use std::fs;
+use std::io::{self, prelude::*, BufReader};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::PathBuf;
+use std::thread;
+
+use crate::systray::{self, Cmd};
+
+/// Send `cmd` to the running instance, returns `false` if there is none
+pub fn forward(cmd: Cmd) -> bool {
+    let r: io::Result<()> = try {
+        let mut stream = UnixStream::connect(socket_path())?;
+        writeln!(stream, "{}", cmd_to_string(&cmd))?;
+    };
+    r.is_ok()
+}
+
+/// Become the running instance and handle commands from others,
+/// returns `false` if another instance is already running
+pub fn listen() -> io::Result<bool> {
+    let path = socket_path();
+    let listener = match UnixListener::bind(&path) {
+        Ok(listener) => listener,
+        Err(e) if e.kind() == io::ErrorKind::AddrInUse => {
+            if UnixStream::connect(&path).is_ok() {
+                return Ok(false);
+            }
+            // Left by a crashed instance
+            fs::remove_file(&path).unwrap_or_default();
+            UnixListener::bind(&path)?
+        }
+        Err(e) => return Err(e),
+    };
+
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let stream = match stream {
+                Ok(stream) => stream,
+                Err(_) => continue,
+            };
+            for line in BufReader::new(stream).lines() {
+                match line.ok().as_deref().and_then(cmd_from_str) {
+                    Some(cmd) => systray::emit_cmd(cmd),
+                    None => break,
+                }
+            }
+        }
+    });
+    Ok(true)
+}
+
+fn socket_path() -> PathBuf {
+    let name = concat!(env!("CARGO_PKG_NAME"), ".sock");
+    env::var("XDG_RUNTIME_DIR").map_or_else(
+        |_| env::temp_dir().join(format!("{}-{}", env::var("USER").unwrap_or_default(), name)),
+        |path| PathBuf::from(path).join(name),
+    )
+}
+
+fn cmd_to_string(cmd: &Cmd) -> String {
+    match cmd {
+        Cmd::Open => "open".to_owned(),
+        Cmd::Quit => "quit".to_owned(),
+        Cmd::Next => "next".to_owned(),
+        Cmd::SetById(id) => format!("set {}", id),
+        Cmd::ToggleLike => "like".to_owned(),
+        Cmd::Pause(true) => "pause".to_owned(),
+        Cmd::Pause(false) => "resume".to_owned(),
+        Cmd::SetMode(mode) => format!("mode {}", mode),
+        Cmd::ImportFavorites(path) => format!("import {}", path),
+        // Values may have spaces, keys don't
+        Cmd::SetConfig(key, value) => format!("config {} {}", key, value),
+    }
+}
+
+fn cmd_from_str(s: &str) -> Option<Cmd> {
+    let mut args = s.splitn(2, ' ');
+    match (args.next()?, args.next()) {
+        ("open", None) => Some(Cmd::Open),
+        ("quit", None) => Some(Cmd::Quit),
+        ("next", None) => Some(Cmd::Next),
+        ("set", Some(id)) => Some(Cmd::SetById(id.to_owned())),
+        ("like", None) => Some(Cmd::ToggleLike),
+        ("pause", None) => Some(Cmd::Pause(true)),
+        ("resume", None) => Some(Cmd::Pause(false)),
+        ("mode", Some(mode)) => mode.parse().ok().map(Cmd::SetMode),
+        ("import", Some(path)) => Some(Cmd::ImportFavorites(path.to_owned())),
+        ("config", Some(args)) => {
+            let (key, value) = args.split_once(' ')?;
+            Some(Cmd::SetConfig(key.to_owned(), value.to_owned()))
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn commands_round_trip() {
+        let cmds = [
+            Cmd::Quit,
+            Cmd::SetById("MountFuji".to_owned()),
+            Cmd::Pause(false),
+            Cmd::SetConfig("local_dir".to_owned(), "/a b/c".to_owned()),
+        ];
+        for cmd in &cmds {
+            let parsed = cmd_from_str(&cmd_to_string(cmd)).unwrap();
+            assert_eq!(format!("{:?}", parsed), format!("{:?}", cmd));
+        }
+    }
+
+    #[test]
+    fn forwarded_quit_ends_main() {
+        assert!(crate::keep_running(true, true));
+        // What the listener does with a line from `biying quit`
+        systray::emit_cmd(cmd_from_str(&cmd_to_string(&Cmd::Quit)).unwrap());
+        assert!(!crate::keep_running(true, true));
+    }
+}
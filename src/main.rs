@@ -3,20 +3,36 @@
 #![feature(try_blocks)]
 #![recursion_limit = "1024"]
 
+use std::env;
 use std::fs::{self, File};
 use std::io::prelude::*;
 use std::os::unix::fs::PermissionsExt;
+use std::process;
 
 use cpp::*;
 use cstr::*;
 use qmetaobject::*;
 
 mod async_utils;
+mod cli;
 mod config;
+mod dbus_service;
+mod downloader;
+mod error;
+mod favorites;
+mod http;
 mod implementation;
+mod instance;
 mod listmodel;
+mod monitor;
+mod scale;
+mod scheduler;
+mod setter;
+mod source;
 mod systray;
 
+use config::{Config, DesktopEnviroment};
+
 cpp! {{
     #include <malloc.h>
     #include <QtGui/QIcon>
@@ -47,16 +63,53 @@ qrc! { init_ressource,
 }
 
 fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    if let Some(r) = cli::run(&args) {
+        if let Err(e) = r {
+            eprintln!("{}", e);
+            process::exit(1);
+        }
+        return;
+    }
+
+    // Only change wallpapers in background, the window is created when the tray asks for it
+    let daemon = args.iter().any(|arg| arg == "--daemon");
+    if !daemon && instance::forward(systray::Cmd::Open) {
+        return;
+    }
+    match instance::listen() {
+        Ok(true) => {}
+        Ok(false) => {
+            eprintln!("{} is already running", env!("CARGO_PKG_NAME"));
+            return;
+        }
+        Err(e) => {
+            eprintln!("Failed to listen for other instances: {}", e);
+            process::exit(1);
+        }
+    }
+
     #[cfg(not(debug_assertions))]
     init_ressource();
     qml_register_type::<systray::TrayProxy>(cstr!("TrayProxy"), 1, 0, cstr!("TrayProxy"));
 
     systray::run_tray_in_background();
+    dbus_service::run_in_background();
+
+    if daemon {
+        let config = Config::open().unwrap_or_default();
+        prepare_config(&config);
+        scheduler::update(&config);
+        if !wait_for_open() {
+            return;
+        }
+    }
 
     let mut engine = create_engine();
     loop {
         let wallpapers = QObjectBox::new(create_wallpapers());
         let wallpapers = wallpapers.pinned();
+        wallpapers.borrow().watch_scheduler();
 
         engine.set_object_property("wallpapers".into(), wallpapers.clone());
         engine.load_file(
@@ -67,16 +120,20 @@ fn main() {
         );
 
         engine.exec();
+        scheduler::set_error_reporter(None);
 
-        wallpapers
-            .borrow()
-            .config
-            .borrow()
-            .save()
-            .expect("Failed to save configs");
+        {
+            let wallpapers = wallpapers.borrow();
+            let config = wallpapers.config.borrow();
+            if let Err(e) = config.save() {
+                eprintln!("Failed to save config: {}", e);
+            }
+            // Keep changing wallpaper with the latest settings after the window is closed
+            scheduler::update(&config);
 
-        if !wallpapers.borrow().config.borrow().auto_change.enable {
-            break;
+            if !keep_running(config.auto_change.enable, daemon) {
+                break;
+            }
         }
         let engine_ptr = &mut engine;
 
@@ -85,11 +142,57 @@ fn main() {
             malloc_trim(0);
         });
 
+        if !wait_for_open() {
+            break;
+        }
+    }
+}
+
+/// After the window is closed, keep running in background for auto change,
+/// unless quitting is asked
+fn keep_running(auto_change: bool, daemon: bool) -> bool {
+    !systray::quit_requested() && (auto_change || daemon)
+}
+
+/// Block until the tray asks to open the window, return `false` to quit
+fn wait_for_open() -> bool {
+    loop {
         match systray::wait() {
-            systray::Cmd::Open => {
-                continue;
+            systray::Cmd::Open => return true,
+            systray::Cmd::Quit => return false,
+            cmd => {
+                // No window, config on disk is the latest
+                let mut config = Config::open().unwrap_or_default();
+                prepare_config(&config);
+                let r = match cmd {
+                    systray::Cmd::ToggleLike => {
+                        implementation::toggle_like_current(&mut config).map(|_| ())
+                    }
+                    systray::Cmd::Pause(paused) => {
+                        config.auto_change.enable = !paused;
+                        config.save()
+                    }
+                    systray::Cmd::SetMode(mode) => {
+                        config.auto_change.mode = mode;
+                        config.save()
+                    }
+                    systray::Cmd::ImportFavorites(path) => config
+                        .favorites
+                        .import(path.as_ref(), &config.download_dir)
+                        .and_then(|_| config.save_favorites()),
+                    systray::Cmd::SetConfig(key, value) => {
+                        config.with_value(&key, &value).and_then(|new| {
+                            config = new;
+                            config.save()
+                        })
+                    }
+                    _ => unreachable!("only window commands are waited"),
+                };
+                match r {
+                    Ok(()) => scheduler::update(&config),
+                    Err(e) => eprintln!("{}", e),
+                }
             }
-            systray::Cmd::Quit => break,
         }
     }
 }
@@ -112,37 +215,41 @@ fn create_engine() -> QmlEngine {
 }
 
 fn create_wallpapers() -> implementation::Wallpapers {
+    let wallpapers = implementation::Wallpapers::new();
+    prepare_config(&wallpapers.config.borrow());
+    scheduler::update(&wallpapers.config.borrow());
+
+    wallpapers
+}
+
+/// Install the fallback script, and use it as the default custom command
+fn prepare_config(config: &Config) {
     let set_wallpaper_fallback = include_bytes!(concat!(
         env!("CARGO_MANIFEST_DIR"),
         "/assets/variety/data/scripts/set_wallpaper"
     ));
 
-    let wallpapers = implementation::Wallpapers::new();
-
-    {
-        let config = &wallpapers.config.borrow();
-        if !config.cache_dir.exists() {
-            fs::create_dir_all(&config.cache_dir).expect("create cache_dir");
-        }
-        let fallback_script_path = config.cache_dir.join("set_wallpaper");
-        let mut fallback_script =
-            File::create(&fallback_script_path).expect("create set_wallpaper");
-        fallback_script
-            .write_all(set_wallpaper_fallback)
-            .expect("write set_wallpaper");
-        fallback_script
-            .set_permissions(fs::Permissions::from_mode(0o755))
-            .expect("set 755 for set_wallpaper");
-        let mut de = config.de.borrow_mut();
-        let custom_cmd = &mut de.last_mut().unwrap().cmd;
-        if custom_cmd.to_slice().is_empty() {
-            *custom_cmd = format!(
-                "{} \"$WALLPAPER\" auto",
-                &*fallback_script_path.to_string_lossy()
-            )
-            .into();
-        }
+    let fallback_script_path = config.cache_dir.join("set_wallpaper");
+    let r: Result<(), failure::Error> = try {
+        fs::create_dir_all(&config.cache_dir)?;
+        let mut fallback_script = File::create(&fallback_script_path)?;
+        fallback_script.write_all(set_wallpaper_fallback)?;
+        fallback_script.set_permissions(fs::Permissions::from_mode(0o755))?;
+    };
+    if let Err(e) = r {
+        // Other commands still work
+        eprintln!(
+            "Failed to install {}: {}",
+            fallback_script_path.display(),
+            e
+        );
+    }
+    let mut de = config.de.borrow_mut();
+    let custom = de.last_mut().unwrap();
+    if custom.cmd.to_slice().is_empty() {
+        *custom = DesktopEnviroment::custom(&format!(
+            "{} \"$WALLPAPER\" auto",
+            &*fallback_script_path.to_string_lossy()
+        ));
     }
-
-    wallpapers
 }
@@ -0,0 +1,279 @@
+//! Outputs of the desktop, to choose wallpapers and their resolutions for them
+
+use std::env;
+use std::process::Stdio;
+
+use lazy_static::lazy_static;
+use regex::Regex;
+use serde::Deserialize;
+use tokio::process::Command;
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct Monitor {
+    /// Like `DP-1`
+    pub name: String,
+    pub width: u32,
+    pub height: u32,
+    pub primary: bool,
+}
+
+impl Monitor {
+    pub fn is_portrait(&self) -> bool {
+        self.height > self.width
+    }
+}
+
+/// Connected and enabled outputs in physical pixels, the primary one first.
+/// Asks the compositor on Wayland, or xrandr. Empty if they can't be found
+pub async fn detect() -> Vec<Monitor> {
+    if env::var_os("WAYLAND_DISPLAY").is_some() {
+        if let Some(json) = query("swaymsg", &["-t", "get_outputs", "--raw"]).await {
+            return parse_sway(&json);
+        }
+        if let Some(json) = query("hyprctl", &["monitors", "-j"]).await {
+            return parse_hyprland(&json);
+        }
+    }
+    match query("xrandr", &["--query"]).await {
+        Some(output) => parse_xrandr(&output),
+        None => Vec::new(),
+    }
+}
+
+/// A wallpaper shared by all monitors is made for this one
+pub fn largest(monitors: &[Monitor]) -> Option<&Monitor> {
+    monitors
+        .iter()
+        .max_by_key(|m| m.width as u64 * m.height as u64)
+}
+
+/// The smallest one of `resolutions` covering a `width`x`height` screen,
+/// or the largest one if none does. Ones in another orientation are only used if
+/// there is no other choice
+pub fn best_resolution(resolutions: &[String], width: u32, height: u32) -> Option<String> {
+    let portrait = height > width;
+    let sizes: Vec<(&String, u32, u32)> = resolutions
+        .iter()
+        .filter_map(|res| {
+            let (w, h) = res.split_once('x')?;
+            Some((res, w.parse().ok()?, h.parse().ok()?))
+        })
+        .collect();
+    let same_orientation: Vec<_> = sizes
+        .iter()
+        .filter(|(_, w, h)| (h > w) == portrait)
+        .cloned()
+        .collect();
+    let sizes = if same_orientation.is_empty() {
+        sizes
+    } else {
+        same_orientation
+    };
+    let area = |&(_, w, h): &(&String, u32, u32)| w as u64 * h as u64;
+    sizes
+        .iter()
+        .filter(|(_, w, h)| *w >= width && *h >= height)
+        .min_by_key(|&v| area(v))
+        .or_else(|| sizes.iter().max_by_key(|&v| area(v)))
+        .map(|(res, _, _)| res.to_string())
+}
+
+/// Output of the command, `None` if it's not there or fails
+async fn query(program: &str, args: &[&str]) -> Option<String> {
+    let output = Command::new(program)
+        .args(args)
+        .stdin(Stdio::null())
+        .stderr(Stdio::null())
+        .output()
+        .await
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    Some(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// `swaymsg -t get_outputs`, the mode is in physical pixels before the transform
+fn parse_sway(json: &str) -> Vec<Monitor> {
+    #[derive(Deserialize)]
+    struct Output {
+        name: String,
+        #[serde(default)]
+        active: bool,
+        #[serde(default)]
+        focused: bool,
+        current_mode: Option<Mode>,
+        #[serde(default)]
+        transform: String,
+    }
+    #[derive(Deserialize)]
+    struct Mode {
+        width: u32,
+        height: u32,
+    }
+    let outputs: Vec<Output> = serde_json::from_str(json).unwrap_or_default();
+    let mut monitors: Vec<Monitor> = outputs
+        .into_iter()
+        .filter(|output| output.active)
+        .filter_map(|output| {
+            let mode = output.current_mode?;
+            let rotated = output.transform.contains("90") || output.transform.contains("270");
+            Some(rotate(
+                Monitor {
+                    name: output.name,
+                    width: mode.width,
+                    height: mode.height,
+                    // Sway has no primary output, the focused one is used
+                    primary: output.focused,
+                },
+                rotated,
+            ))
+        })
+        .collect();
+    monitors.sort_by_key(|m| !m.primary);
+    monitors
+}
+
+/// `hyprctl monitors -j`, the size is in physical pixels before the transform
+fn parse_hyprland(json: &str) -> Vec<Monitor> {
+    #[derive(Deserialize)]
+    struct Output {
+        name: String,
+        width: u32,
+        height: u32,
+        #[serde(default)]
+        transform: u8,
+        #[serde(default)]
+        focused: bool,
+    }
+    let outputs: Vec<Output> = serde_json::from_str(json).unwrap_or_default();
+    let mut monitors: Vec<Monitor> = outputs
+        .into_iter()
+        .map(|output| {
+            // 1, 3, 5 and 7 are rotated by 90 or 270 degrees
+            let rotated = output.transform % 2 == 1;
+            let monitor = Monitor {
+                name: output.name,
+                width: output.width,
+                height: output.height,
+                primary: output.focused,
+            };
+            rotate(monitor, rotated)
+        })
+        .collect();
+    monitors.sort_by_key(|m| !m.primary);
+    monitors
+}
+
+fn rotate(mut monitor: Monitor, rotated: bool) -> Monitor {
+    if rotated {
+        std::mem::swap(&mut monitor.width, &mut monitor.height);
+    }
+    monitor
+}
+
+/// The size in `xrandr --query` is already rotated
+fn parse_xrandr(s: &str) -> Vec<Monitor> {
+    lazy_static! {
+        static ref OUTPUT: Regex =
+            Regex::new(r"^(\S+) connected (primary )?(\d+)x(\d+)\+\d+\+\d+").unwrap();
+    }
+    let mut monitors: Vec<Monitor> = s
+        .lines()
+        .filter_map(|line| {
+            let caps = OUTPUT.captures(line)?;
+            Some(Monitor {
+                name: caps[1].to_owned(),
+                width: caps[3].parse().ok()?,
+                height: caps[4].parse().ok()?,
+                primary: caps.get(2).is_some(),
+            })
+        })
+        .collect();
+    // Stable, so others keep the order of xrandr
+    monitors.sort_by_key(|m| !m.primary);
+    monitors
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// eDP-1 and HDMI-1 turned left as the primary, DP-2 is connected but off
+    const XRANDR: &str = include_str!("testdata/xrandr.txt");
+    /// DP-3 is turned by 90 degrees and focused, HDMI-A-1 is off
+    const SWAY: &str = include_str!("testdata/sway_outputs.json");
+    /// DP-2 is turned by 270 degrees and focused
+    const HYPRLAND: &str = include_str!("testdata/hyprland_monitors.json");
+
+    fn monitor(name: &str, width: u32, height: u32, primary: bool) -> Monitor {
+        Monitor {
+            name: name.to_owned(),
+            width,
+            height,
+            primary,
+        }
+    }
+
+    fn resolutions(list: &[&str]) -> Vec<String> {
+        list.iter().map(|res| res.to_string()).collect()
+    }
+
+    #[test]
+    fn xrandr() {
+        assert_eq!(
+            parse_xrandr(XRANDR),
+            [
+                monitor("HDMI-1", 1080, 1920, true),
+                monitor("eDP-1", 1920, 1080, false),
+            ]
+        );
+    }
+
+    #[test]
+    fn sway() {
+        assert_eq!(
+            parse_sway(SWAY),
+            [
+                monitor("DP-3", 1440, 2560, true),
+                monitor("eDP-1", 2560, 1600, false),
+            ]
+        );
+        assert!(parse_sway("not json").is_empty());
+    }
+
+    #[test]
+    fn hyprland() {
+        assert_eq!(
+            parse_hyprland(HYPRLAND),
+            [
+                monitor("DP-2", 2160, 3840, true),
+                monitor("eDP-1", 1920, 1200, false),
+            ]
+        );
+    }
+
+    #[test]
+    fn smallest_covering_resolution() {
+        let list = resolutions(&["1920x1080", "1366x768", "1080x1920", "768x1280", "original"]);
+        let best = |w, h| best_resolution(&list, w, h).unwrap();
+        assert_eq!(best(1366, 768), "1366x768");
+        assert_eq!(best(1600, 900), "1920x1080");
+        // None is large enough
+        assert_eq!(best(2560, 1440), "1920x1080");
+        assert_eq!(best(720, 1280), "768x1280");
+        assert_eq!(best(1440, 2560), "1080x1920");
+    }
+
+    #[test]
+    fn other_orientation_only_without_choice() {
+        let landscape = resolutions(&["1920x1080", "1366x768"]);
+        assert_eq!(
+            best_resolution(&landscape, 1080, 1920).unwrap(),
+            "1920x1080"
+        );
+        let portrait = resolutions(&["1080x1920", "768x1280"]);
+        assert_eq!(best_resolution(&portrait, 1366, 768).unwrap(), "1080x1920");
+        assert_eq!(best_resolution(&[], 1366, 768), None);
+    }
+}
@@ -0,0 +1,193 @@
+//! Variants of downloaded images in the exact size of a screen,
+//! so desktops don't stretch or letterbox them
+
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use failure::{self, format_err};
+use image::imageops::{self, FilterType};
+use image::{DynamicImage, GenericImageView, ImageOutputFormat};
+use lazy_static::lazy_static;
+use regex::Regex;
+
+/// How much the crop prefers the center, from 0 to 1
+const CENTER_BIAS: f64 = 0.3;
+/// The background of `Fit` is this much smaller before it's stretched, to blur it
+const BLUR_FACTOR: u32 = 32;
+const JPEG_QUALITY: u8 = 90;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Mode {
+    /// Cover the screen, cropped at the center
+    Fill,
+    /// The whole image, with bars of its blurred self
+    Fit,
+    /// Cover the screen, cropped where the details are
+    SmartCrop,
+}
+
+impl Mode {
+    /// The index in settings, 0 is off
+    pub fn from_index(index: u8) -> Option<Mode> {
+        match index {
+            1 => Some(Mode::Fill),
+            2 => Some(Mode::Fit),
+            3 => Some(Mode::SmartCrop),
+            _ => None,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Mode::Fill => "fill",
+            Mode::Fit => "fit",
+            Mode::SmartCrop => "smart",
+        }
+    }
+}
+
+/// `file` in `width`x`height`, kept in `output_dir` as `{file name}.{width}x{height}-{mode}.jpg`
+/// and made again if `file` is changed. `file` itself if it's already in that size
+pub async fn scaled(
+    file: &Path,
+    output_dir: &Path,
+    mode: Mode,
+    width: u32,
+    height: u32,
+) -> Result<PathBuf, failure::Error> {
+    // The whole name, `a.jpg` and `a.png` in the local folder are different images
+    let file_name = file
+        .file_name()
+        .and_then(|name| name.to_str())
+        .ok_or_else(|| format_err!("Invalid file name: {}", file.display()))?;
+    let name = format!("{}.{}x{}-{}.jpg", file_name, width, height, mode.name());
+    let output = output_dir.join(name);
+    let modified = |path: &Path| fs::metadata(path).and_then(|m| m.modified()).ok();
+    if let Some(made) = modified(&output) {
+        if modified(file).map_or(true, |changed| changed <= made) {
+            return Ok(output);
+        }
+    }
+
+    fs::create_dir_all(output_dir)?;
+    let file = file.to_owned();
+    // Decoding and resizing take a while, keep them off the runtime
+    tokio::task::spawn_blocking(move || -> Result<PathBuf, failure::Error> {
+        let img = image::io::Reader::open(&file)?
+            .with_guessed_format()?
+            .decode()?;
+        if img.dimensions() == (width, height) {
+            return Ok(file);
+        }
+        let img = match mode {
+            Mode::Fill => cover(&img, width, height, false),
+            Mode::Fit => fit(&img, width, height),
+            Mode::SmartCrop => cover(&img, width, height, true),
+        };
+        // Written aside first, so a half written one is never used
+        let tmp = output.with_extension("tmp");
+        let mut writer = BufWriter::new(File::create(&tmp)?);
+        img.write_to(&mut writer, ImageOutputFormat::Jpeg(JPEG_QUALITY))?;
+        writer.flush()?;
+        drop(writer);
+        fs::rename(&tmp, &output)?;
+        Ok(output)
+    })
+    .await?
+}
+
+/// Made by `scaled`
+pub fn is_scaled(file_name: &str) -> bool {
+    lazy_static! {
+        static ref SCALED_FILE_NAME: Regex =
+            Regex::new(r"\.\d+x\d+-(fill|fit|smart)\.jpg$").unwrap();
+    }
+    SCALED_FILE_NAME.is_match(file_name)
+}
+
+/// Crop `img` to the aspect ratio of `width`x`height`, and resize it to that.
+/// With `smart` the crop is moved to where the details are, instead of the center
+fn cover(img: &DynamicImage, width: u32, height: u32, smart: bool) -> DynamicImage {
+    let (img_width, img_height) = img.dimensions();
+    let scale = f64::max(
+        width as f64 / img_width as f64,
+        height as f64 / img_height as f64,
+    );
+    let crop_width = ((width as f64 / scale).round() as u32).clamp(1, img_width);
+    let crop_height = ((height as f64 / scale).round() as u32).clamp(1, img_height);
+    // Only one side is cropped
+    let horizontal = crop_width < img_width;
+    let (len, window) = if horizontal {
+        (img_width, crop_width)
+    } else {
+        (img_height, crop_height)
+    };
+    let start = if smart && window < len {
+        interest(&details(img, horizontal), window as usize) as u32
+    } else {
+        (len - window) / 2
+    };
+    let (x, y) = if horizontal { (start, 0) } else { (0, start) };
+    img.crop_imm(x, y, crop_width, crop_height)
+        .resize_exact(width, height, FilterType::Lanczos3)
+}
+
+/// `img` inside `width`x`height`, the rest is filled by a blurred and cropped `img`
+fn fit(img: &DynamicImage, width: u32, height: u32) -> DynamicImage {
+    let small = cover(
+        img,
+        (width / BLUR_FACTOR).max(1),
+        (height / BLUR_FACTOR).max(1),
+        false,
+    );
+    let mut background = small
+        .resize_exact(width, height, FilterType::Triangle)
+        .to_rgb8();
+    let foreground = img.resize(width, height, FilterType::Lanczos3).to_rgb8();
+    let x = (width - foreground.width()) / 2;
+    let y = (height - foreground.height()) / 2;
+    imageops::overlay(&mut background, &foreground, x, y);
+    DynamicImage::ImageRgb8(background)
+}
+
+/// How much details are in every column of `img` if `horizontal`, or every row,
+/// measured by differences between neighbouring pixels
+fn details(img: &DynamicImage, horizontal: bool) -> Vec<u64> {
+    let luma = img.to_luma8();
+    let (width, height) = luma.dimensions();
+    let len = if horizontal { width } else { height };
+    let mut details = vec![0; len as usize];
+    for y in 0..height {
+        for x in 0..width {
+            let pixel = luma.get_pixel(x, y)[0] as i32;
+            let mut diff = 0;
+            if x + 1 < width {
+                diff += (luma.get_pixel(x + 1, y)[0] as i32 - pixel).abs();
+            }
+            if y + 1 < height {
+                diff += (luma.get_pixel(x, y + 1)[0] as i32 - pixel).abs();
+            }
+            let i = if horizontal { x } else { y };
+            details[i as usize] += diff as u64;
+        }
+    }
+    details
+}
+
+/// The start of the `window` long range with the most details, a little biased to the center
+fn interest(details: &[u64], window: usize) -> usize {
+    let mut sums = vec![0u64; details.len() + 1];
+    for (i, d) in details.iter().enumerate() {
+        sums[i + 1] = sums[i] + d;
+    }
+    let center = (details.len() - window) as f64 / 2.0;
+    let score = |start: usize| {
+        let sum = (sums[start + window] - sums[start]) as f64;
+        let distance = (start as f64 - center).abs() / center.max(1.0);
+        sum * (1.0 - CENTER_BIAS * distance)
+    };
+    (0..=details.len() - window)
+        .max_by(|&a, &b| score(a).partial_cmp(&score(b)).unwrap())
+        .unwrap_or_default()
+}
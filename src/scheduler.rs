@@ -0,0 +1,118 @@
+use std::sync::Mutex;
+use std::time::Duration;
+
+use futures::future::pending;
+use lazy_static::lazy_static;
+use tokio::sync::{watch, Notify};
+use tokio::time::{self, Instant};
+
+use crate::async_utils;
+use crate::config::{Config, Snapshot};
+use crate::error::Error;
+use crate::implementation;
+use crate::source;
+
+lazy_static! {
+    /// Latest settings, the scheduler task is started on first access
+    static ref SNAPSHOT: watch::Sender<Option<Snapshot>> = {
+        let (sender, recver) = watch::channel(None);
+        async_utils::spawn(run(recver));
+        sender
+    };
+    /// Change wallpaper now, instead of waiting for the interval
+    static ref NEXT: Notify = Notify::new();
+    /// Send errors to Wallpapers, if the QML engine is running
+    static ref ERROR_REPORTER: Mutex<Option<Box<dyn Fn(Error) + Send>>> = Mutex::default();
+}
+
+/// Apply new settings, changes of interval and mode take effect immediately
+pub fn update(config: &Config) {
+    let snapshot = config.snapshot();
+    source::configure(&snapshot);
+    // The receiver lives as long as the runtime, so this never fails
+    let _ = SNAPSHOT.send(Some(snapshot));
+}
+
+/// Change to the next wallpaper now, the interval is counted from here
+pub fn next() {
+    // Make sure the scheduler is running
+    lazy_static::initialize(&SNAPSHOT);
+    NEXT.notify_one();
+}
+
+/// Set the wallpaper with this ID, using the latest settings
+pub fn set_by_id(id: String) {
+    if let Some(snapshot) = snapshot() {
+        async_utils::spawn(async move {
+            if let Err(e) = implementation::set_wallpaper_by_id(&snapshot, &id).await {
+                report_error(e);
+            }
+        });
+    }
+}
+
+/// Watch the latest settings
+pub fn subscribe() -> watch::Receiver<Option<Snapshot>> {
+    SNAPSHOT.subscribe()
+}
+
+/// The latest settings, `None` if there is no config loaded yet
+pub fn snapshot() -> Option<Snapshot> {
+    SNAPSHOT.borrow().clone()
+}
+
+pub fn set_error_reporter(reporter: Option<Box<dyn Fn(Error) + Send>>) {
+    *ERROR_REPORTER.lock().unwrap() = reporter;
+}
+
+fn report_error(e: failure::Error) {
+    let reporter = ERROR_REPORTER.lock().unwrap();
+    match &*reporter {
+        Some(report) => (report)(e.into()),
+        None => eprintln!("Failed to change wallpaper: {}", e),
+    }
+}
+
+async fn run(mut recver: watch::Receiver<Option<Snapshot>>) {
+    // Counting from the last change, or the moment it's enabled
+    let mut last_change: Option<Instant> = None;
+    loop {
+        let snapshot = recver.borrow().clone();
+        let deadline = match &snapshot {
+            Some(s) if s.auto_change.enable => {
+                let interval = Duration::from_secs(s.auto_change.interval as u64 * 60);
+                Some(*last_change.get_or_insert_with(Instant::now) + interval)
+            }
+            _ => {
+                last_change = None;
+                None
+            }
+        };
+        let timeout = async {
+            match deadline {
+                Some(deadline) => time::sleep_until(deadline).await,
+                None => pending().await,
+            }
+        };
+
+        tokio::select! {
+            r = recver.changed() => {
+                if r.is_err() {
+                    return;
+                }
+                continue;
+            }
+            _ = timeout => (),
+            _ = NEXT.notified() => (),
+        }
+
+        if deadline.is_some() {
+            last_change = Some(Instant::now());
+        }
+        if let Some(snapshot) = snapshot {
+            if let Err(e) = implementation::next_wallpaper(&snapshot).await {
+                report_error(e);
+            }
+        }
+    }
+}
@@ -0,0 +1,158 @@
+use std::env;
+use std::fmt;
+use std::path::Path;
+
+use async_trait::async_trait;
+use tokio::process::Command;
+
+use super::{file_uri, run, WallpaperSetter};
+use crate::error::Error;
+
+/// Programs run in order without a shell. In their arguments `{wallpaper}` is replaced by
+/// the path, `{uri}` by its `file://` URI and `{monitor}` by the name of the monitor
+pub struct Argv {
+    commands: Vec<Vec<String>>,
+    /// What `{monitor}` means all monitors, arguments with it are left out if there's none
+    all_monitors: Option<&'static str>,
+}
+
+impl Argv {
+    pub fn new(commands: &[&[&str]]) -> Self {
+        Argv {
+            commands: commands
+                .iter()
+                .map(|args| args.iter().map(|arg| arg.to_string()).collect())
+                .collect(),
+            all_monitors: None,
+        }
+    }
+
+    pub fn all_monitors(mut self, all: &'static str) -> Self {
+        self.all_monitors = Some(all);
+        self
+    }
+
+    /// The commands with templates replaced
+    fn expand(&self, wallpaper: &str, uri: &str, monitor: Option<&str>) -> Vec<Vec<String>> {
+        let monitor = monitor.or(self.all_monitors);
+        self.commands
+            .iter()
+            .map(|args| {
+                args.iter()
+                    .filter(|arg| monitor.is_some() || !arg.contains("{monitor}"))
+                    .map(|arg| {
+                        arg.replace("{wallpaper}", wallpaper)
+                            .replace("{uri}", uri)
+                            .replace("{monitor}", monitor.unwrap_or_default())
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    fn program(&self) -> &str {
+        self.commands
+            .first()
+            .and_then(|args| args.first())
+            .map_or("", |program| &**program)
+    }
+}
+
+#[async_trait]
+impl WallpaperSetter for Argv {
+    async fn apply(&self, file: &Path, monitor: Option<&str>) -> Result<(), failure::Error> {
+        let uri = file_uri(file)?;
+        for args in self.expand(&file.to_string_lossy(), &uri, monitor) {
+            let mut command = Command::new(&args[0]);
+            command.args(&args[1..]);
+            run(&mut command, &args.join(" ")).await?;
+        }
+        Ok(())
+    }
+
+    fn supports_monitors(&self) -> bool {
+        self.commands
+            .iter()
+            .flatten()
+            .any(|arg| arg.contains("{monitor}"))
+    }
+}
+
+impl fmt::Display for Argv {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let commands: Vec<String> = self.commands.iter().map(|args| args.join(" ")).collect();
+        f.write_str(&commands.join(" && "))
+    }
+}
+
+/// The first one whose program is installed, for desktops with several tools to choose from
+pub struct FirstInstalled(pub Vec<Argv>);
+
+impl FirstInstalled {
+    fn installed(&self) -> Result<&Argv, Error> {
+        let paths = env::var_os("PATH").unwrap_or_default();
+        let found = |program: &str| env::split_paths(&paths).any(|p| p.join(program).is_file());
+        self.0
+            .iter()
+            .find(|argv| found(argv.program()))
+            .ok_or_else(|| {
+                let programs: Vec<&str> = self.0.iter().map(Argv::program).collect();
+                Error::Command(format!("None of {} is installed", programs.join(", ")))
+            })
+    }
+}
+
+#[async_trait]
+impl WallpaperSetter for FirstInstalled {
+    async fn apply(&self, file: &Path, monitor: Option<&str>) -> Result<(), failure::Error> {
+        self.installed()?.apply(file, monitor).await
+    }
+
+    fn supports_monitors(&self) -> bool {
+        self.installed().map_or(false, Argv::supports_monitors)
+    }
+}
+
+impl fmt::Display for FirstInstalled {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let alternatives: Vec<String> = self.0.iter().map(Argv::to_string).collect();
+        f.write_str(&alternatives.join(" || "))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn templates() {
+        let argv = Argv::new(&[
+            &["swww", "img", "{wallpaper}", "--outputs={monitor}"],
+            &["notify", "{uri}"],
+        ]);
+        assert_eq!(
+            argv.expand("/a b/c.jpg", "file:///a%20b/c.jpg", Some("DP-1")),
+            [
+                vec!["swww", "img", "/a b/c.jpg", "--outputs=DP-1"],
+                vec!["notify", "file:///a%20b/c.jpg"],
+            ]
+        );
+        assert!(argv.supports_monitors());
+    }
+
+    #[test]
+    fn monitor_left_out() {
+        let argv = Argv::new(&[&["swww", "img", "{wallpaper}", "--outputs={monitor}"]]);
+        assert_eq!(
+            argv.expand("/a.jpg", "file:///a.jpg", None),
+            [vec!["swww", "img", "/a.jpg"]]
+        );
+        let argv =
+            Argv::new(&[&["swaybg", "-o", "{monitor}", "-i", "{wallpaper}"]]).all_monitors("*");
+        assert_eq!(
+            argv.expand("/a.jpg", "file:///a.jpg", None),
+            [vec!["swaybg", "-o", "*", "-i", "/a.jpg"]]
+        );
+        assert!(!Argv::new(&[&["feh", "--bg-fill", "{wallpaper}"]]).supports_monitors());
+    }
+}
@@ -0,0 +1,47 @@
+use std::fmt;
+use std::path::Path;
+
+use async_trait::async_trait;
+use tokio::process::Command;
+
+use super::{file_uri, run, WallpaperSetter};
+
+/// A key of gsettings, stored in dconf by GNOME and desktops based on it
+pub struct GSettings {
+    schema: &'static str,
+    key: &'static str,
+    /// The key takes a `file://` URI instead of a path
+    uri: bool,
+}
+
+impl GSettings {
+    pub fn new(schema: &'static str, key: &'static str, uri: bool) -> Self {
+        GSettings { schema, key, uri }
+    }
+}
+
+#[async_trait]
+impl WallpaperSetter for GSettings {
+    async fn apply(&self, file: &Path, _monitor: Option<&str>) -> Result<(), failure::Error> {
+        let value = if self.uri {
+            file_uri(file)?
+        } else {
+            file.to_string_lossy().into_owned()
+        };
+        let mut command = Command::new("gsettings");
+        command.args(&["set", self.schema, self.key, &value]);
+        run(&mut command, &self.to_string()).await?;
+        Ok(())
+    }
+
+    fn supports_monitors(&self) -> bool {
+        false
+    }
+}
+
+impl fmt::Display for GSettings {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let value = if self.uri { "{uri}" } else { "{wallpaper}" };
+        write!(f, "gsettings set {} {} {}", self.schema, self.key, value)
+    }
+}
@@ -0,0 +1,114 @@
+//! Ways to set wallpaper, every desktop uses one of them
+
+use std::fmt;
+use std::path::Path;
+use std::process::Stdio;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use async_trait::async_trait;
+use failure::Fail;
+use futures::future;
+use tokio::io::{AsyncRead, AsyncReadExt};
+use tokio::task::JoinHandle;
+use tokio::{process::Command, time};
+
+use crate::error::Error;
+
+mod argv;
+mod gsettings;
+mod plasma;
+mod shell;
+mod xfconf;
+
+pub use argv::{Argv, FirstInstalled};
+pub use gsettings::GSettings;
+pub use plasma::Plasma;
+pub use shell::Shell;
+pub use xfconf::Xfconf;
+
+const COMMAND_TIMEOUT: Duration = Duration::from_secs(30);
+/// How long the output is still read after the command exits
+const OUTPUT_GRACE: Duration = Duration::from_millis(500);
+const OUTPUT_LIMIT: usize = 64 * 1024;
+
+/// Displayed as what it runs, in settings and errors
+#[async_trait]
+pub trait WallpaperSetter: fmt::Display + Send + Sync {
+    /// Set `file` as the wallpaper of `monitor`, or of all monitors if it's `None`
+    /// or not supported. `UnknownMonitor` if the desktop has no monitor by that name
+    async fn apply(&self, file: &Path, monitor: Option<&str>) -> Result<(), failure::Error>;
+    /// `apply` can set monitors one by one
+    fn supports_monitors(&self) -> bool;
+}
+
+/// The desktop doesn't know a monitor by this name,
+/// so it can only be set with the others by `apply(file, None)`
+#[derive(Fail, Debug)]
+#[fail(display = "No monitor named {}", _0)]
+pub struct UnknownMonitor(pub String);
+
+/// Wait for `command`, its stdout if it succeeds. `name` is for errors
+async fn run(command: &mut Command, name: &str) -> Result<String, failure::Error> {
+    let mut child = command
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()
+        .map_err(|e| Error::Command(format!("{}: {}", name, e)))?;
+    let (stdout, stdout_closed) = read_all(child.stdout.take().unwrap());
+    let (stderr, stderr_closed) = read_all(child.stderr.take().unwrap());
+    let status = match time::timeout(COMMAND_TIMEOUT, child.wait()).await {
+        Ok(status) => status.map_err(|e| Error::Command(format!("{}: {}", name, e)))?,
+        Err(_) => {
+            let msg = format!("`{}` didn't finish in {:?}", name, COMMAND_TIMEOUT);
+            return Err(Error::Command(msg).into());
+        }
+    };
+    // Background processes started by it, like `swaybg &`, may keep the output open
+    let _ = time::timeout(OUTPUT_GRACE, future::join(stdout_closed, stderr_closed)).await;
+    let stdout = String::from_utf8_lossy(&stdout.lock().unwrap()).into_owned();
+    let stderr = String::from_utf8_lossy(&stderr.lock().unwrap()).into_owned();
+    if !status.success() {
+        let mut msg = format!("`{}` {}", name, status);
+        for out in &[stderr, stdout] {
+            if !out.trim().is_empty() {
+                msg += "\n";
+                msg += out.trim();
+            }
+        }
+        return Err(Error::Command(msg).into());
+    }
+    Ok(stdout)
+}
+
+/// Read `pipe` until it's closed, which can be long after the command exits.
+/// Only the first `OUTPUT_LIMIT` bytes are kept
+fn read_all(
+    mut pipe: impl AsyncRead + Unpin + Send + 'static,
+) -> (Arc<Mutex<Vec<u8>>>, JoinHandle<()>) {
+    let output = Arc::new(Mutex::new(Vec::new()));
+    let kept = output.clone();
+    let closed = tokio::spawn(async move {
+        let mut buf = [0; 4096];
+        loop {
+            match pipe.read(&mut buf).await {
+                Ok(0) | Err(_) => break,
+                Ok(n) => {
+                    let mut kept = kept.lock().unwrap();
+                    let n = n.min(OUTPUT_LIMIT.saturating_sub(kept.len()));
+                    kept.extend_from_slice(&buf[..n]);
+                }
+            }
+        }
+    });
+    (output, closed)
+}
+
+/// `file://` URI of `file`, special characters are escaped
+fn file_uri(file: &Path) -> Result<String, failure::Error> {
+    let uri = reqwest::Url::from_file_path(file)
+        .map_err(|_| Error::Command(format!("Not an absolute path: {}", file.display())))?;
+    Ok(uri.into())
+}
@@ -0,0 +1,58 @@
+use std::fmt;
+use std::path::Path;
+
+use async_trait::async_trait;
+use dbus::blocking::Connection;
+
+use super::{file_uri, WallpaperSetter, COMMAND_TIMEOUT};
+use crate::error::Error;
+
+/// Every desktop of KDE Plasma, by its scripting API over D-Bus
+pub struct Plasma;
+
+const CONFIG_GROUP: &str = "['Wallpaper', 'org.kde.image', 'General']";
+
+impl Plasma {
+    async fn evaluate(script: String) -> Result<String, failure::Error> {
+        // Blocking calls of `dbus`, with a timeout
+        let output = tokio::task::spawn_blocking(move || -> Result<String, dbus::Error> {
+            let conn = Connection::new_session()?;
+            let proxy = conn.with_proxy("org.kde.plasmashell", "/PlasmaShell", COMMAND_TIMEOUT);
+            let (output,): (String,) =
+                proxy.method_call("org.kde.PlasmaShell", "evaluateScript", (script,))?;
+            Ok(output)
+        })
+        .await?;
+        output.map_err(|e| Error::Command(format!("plasmashell: {}", e)).into())
+    }
+}
+
+#[async_trait]
+impl WallpaperSetter for Plasma {
+    async fn apply(&self, file: &Path, _monitor: Option<&str>) -> Result<(), failure::Error> {
+        // A JSON string is a JavaScript string, whatever is in the path
+        let uri = serde_json::to_string(&file_uri(file)?)?;
+        let script = format!(
+            "var all = desktops();
+            for (var i = 0; i < all.length; i++) {{
+                all[i].wallpaperPlugin = 'org.kde.image';
+                all[i].currentConfigGroup = {};
+                all[i].writeConfig('Image', {});
+            }}",
+            CONFIG_GROUP, uri
+        );
+        Self::evaluate(script).await?;
+        Ok(())
+    }
+
+    /// Desktops only know the index of their screen, not its name
+    fn supports_monitors(&self) -> bool {
+        false
+    }
+}
+
+impl fmt::Display for Plasma {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("org.kde.PlasmaShell.evaluateScript: desktops()[*].writeConfig('Image', {uri})")
+    }
+}
@@ -0,0 +1,45 @@
+use std::fmt;
+use std::path::Path;
+
+use async_trait::async_trait;
+use tokio::process::Command;
+
+use super::{run, WallpaperSetter};
+
+/// A command of the user run by `sh`, with `$WALLPAPER` and `$MONITOR` in the environment.
+/// The last resort, for desktops not known here
+pub struct Shell {
+    cmd: String,
+}
+
+impl Shell {
+    pub fn new(cmd: &str) -> Self {
+        Shell {
+            cmd: cmd.to_owned(),
+        }
+    }
+}
+
+#[async_trait]
+impl WallpaperSetter for Shell {
+    async fn apply(&self, file: &Path, monitor: Option<&str>) -> Result<(), failure::Error> {
+        let mut command = Command::new("sh");
+        command
+            .env("WALLPAPER", file)
+            .env("MONITOR", monitor.unwrap_or_default())
+            .arg("-c")
+            .arg(&self.cmd);
+        run(&mut command, &self.cmd).await?;
+        Ok(())
+    }
+
+    fn supports_monitors(&self) -> bool {
+        self.cmd.contains("$MONITOR") || self.cmd.contains("${MONITOR}")
+    }
+}
+
+impl fmt::Display for Shell {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.cmd)
+    }
+}
@@ -0,0 +1,118 @@
+use std::fmt;
+use std::path::Path;
+
+use async_trait::async_trait;
+use lazy_static::lazy_static;
+use regex::Regex;
+use tokio::process::Command;
+
+use super::{run, UnknownMonitor, WallpaperSetter};
+use crate::error::Error;
+
+/// XFCE keeps a wallpaper for every workspace of every monitor in xfconf
+pub struct Xfconf;
+
+const CHANNEL: &str = "xfce4-desktop";
+
+impl Xfconf {
+    /// Properties of the wallpapers of `monitor`, or of all monitors if it's `None`.
+    /// `UnknownMonitor` if XFCE names it differently, like `monitor0`
+    async fn properties(monitor: Option<&str>) -> Result<Vec<String>, failure::Error> {
+        let mut command = Command::new("xfconf-query");
+        command.args(&["-c", CHANNEL, "-l"]);
+        let output = run(&mut command, "xfconf-query -l").await?;
+        find_properties(&output, monitor)
+    }
+}
+
+/// Like `Xfconf::properties`, in the output of `xfconf-query -l`
+fn find_properties(list: &str, monitor: Option<&str>) -> Result<Vec<String>, failure::Error> {
+    lazy_static! {
+        static ref LAST_IMAGE: Regex =
+            Regex::new(r"^/backdrop/screen\d+/monitor([^/]+)/workspace\d+/last-image$").unwrap();
+    }
+    let all: Vec<(&str, &str)> = list
+        .lines()
+        .filter_map(|line| {
+            let caps = LAST_IMAGE.captures(line)?;
+            Some((line, caps.get(1)?.as_str()))
+        })
+        .collect();
+    if all.is_empty() {
+        let msg = format!("No wallpaper in {}", CHANNEL);
+        return Err(Error::Command(msg).into());
+    }
+    let properties: Vec<String> = all
+        .iter()
+        .filter(|(_, name)| monitor.map_or(true, |monitor| *name == monitor))
+        .map(|(property, _)| property.to_string())
+        .collect();
+    match monitor {
+        Some(monitor) if properties.is_empty() => Err(UnknownMonitor(monitor.to_owned()).into()),
+        _ => Ok(properties),
+    }
+}
+
+#[async_trait]
+impl WallpaperSetter for Xfconf {
+    async fn apply(&self, file: &Path, monitor: Option<&str>) -> Result<(), failure::Error> {
+        for property in Self::properties(monitor).await? {
+            let mut command = Command::new("xfconf-query");
+            command
+                .args(&["-c", CHANNEL, "-p", &property, "-s"])
+                .arg(file);
+            run(&mut command, &format!("xfconf-query -p {}", property)).await?;
+        }
+        Ok(())
+    }
+
+    fn supports_monitors(&self) -> bool {
+        true
+    }
+}
+
+impl fmt::Display for Xfconf {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "xfconf-query -c {} -p /backdrop/screen*/monitor{{monitor}}/workspace*/last-image -s {{wallpaper}}",
+            CHANNEL
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `xfconf-query -c xfce4-desktop -l` of XFCE 4.16 with two monitors
+    const LIST: &str = include_str!("testdata/xfconf-query.txt");
+
+    #[test]
+    fn properties_of_a_monitor() {
+        assert_eq!(
+            find_properties(LIST, Some("HDMI-1")).unwrap(),
+            [
+                "/backdrop/screen0/monitorHDMI-1/workspace0/last-image",
+                "/backdrop/screen0/monitorHDMI-1/workspace1/last-image",
+            ]
+        );
+        assert_eq!(
+            find_properties(LIST, Some("eDP-1")).unwrap(),
+            ["/backdrop/screen0/monitoreDP-1/workspace0/last-image"]
+        );
+    }
+
+    #[test]
+    fn properties_of_all_monitors() {
+        assert_eq!(find_properties(LIST, None).unwrap().len(), 3);
+    }
+
+    #[test]
+    fn unknown_monitor() {
+        let e = find_properties(LIST, Some("monitor0")).unwrap_err();
+        assert!(e.downcast_ref::<UnknownMonitor>().is_some());
+        let e = find_properties("/backdrop/single-workspace-mode\n", None).unwrap_err();
+        assert!(e.downcast_ref::<UnknownMonitor>().is_none());
+    }
+}
@@ -0,0 +1,269 @@
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use async_trait::async_trait;
+use chrono::prelude::*;
+use failure::{self, format_err};
+use futures::future::join_all;
+use rand::Rng;
+use reqwest;
+use serde::Deserialize;
+
+use super::{ImageMeta, ImagePointer, RawImage, SourceKind, WallpaperSource};
+use crate::http;
+
+const API: &str = "https://www.bing.com/HPImageArchive.aspx";
+const CDN_BASE: &str = "https://www.bing.com";
+/// The first one is used for `RawImage::copyright`
+const MARKETS: &[&str] = &[
+    "en-US", "zh-CN", "ja-JP", "en-GB", "de-DE", "fr-FR", "en-CA", "en-AU", "en-IN", "pt-BR",
+];
+/// Bing only returns 8 images a time, `idx` is at most 7 and nothing older than 15 days
+/// is kept, so two pages cover the whole archive. They overlap, `merge` dedupes them
+const PAGES: &[usize] = &[0, 7];
+const CACHE_TIMEOUT: Duration = Duration::from_secs(60 * 60);
+
+/// Bing's own `HPImageArchive`, only the last two weeks are available
+pub struct Bing {
+    cache: Mutex<Option<(Instant, Vec<RawImage>)>>,
+}
+
+impl Bing {
+    pub fn new() -> Self {
+        Bing {
+            cache: Mutex::new(None),
+        }
+    }
+
+    /// All images in the archive of every market, newest first
+    async fn archive(&self) -> Result<Vec<RawImage>, failure::Error> {
+        if let Some((time, images)) = &*self.cache.lock().unwrap() {
+            if time.elapsed() < CACHE_TIMEOUT {
+                return Ok(images.clone());
+            }
+        }
+
+        let requests = MARKETS
+            .iter()
+            .flat_map(|market| PAGES.iter().map(move |idx| self.fetch(market, *idx)));
+        // Markets that failed are left out, unless all of them failed
+        let mut pages = Vec::new();
+        let mut error = None;
+        for r in join_all(requests).await {
+            match r {
+                Ok(page) => pages.push(page),
+                Err(e) => error = Some(e),
+            }
+        }
+        if let (true, Some(e)) = (pages.is_empty(), error) {
+            return Err(e);
+        }
+        let images = merge(pages);
+
+        *self.cache.lock().unwrap() = Some((Instant::now(), images.clone()));
+        Ok(images)
+    }
+
+    async fn fetch(&self, market: &str, idx: usize) -> Result<Vec<RawImage>, failure::Error> {
+        let idx = idx.to_string();
+        let url = reqwest::Url::parse_with_params(
+            API,
+            &[("format", "js"), ("idx", &idx), ("n", "8"), ("mkt", market)],
+        )
+        .expect("parse url");
+        let json = http::send(http::client().get(url)).await?.bytes().await?;
+        parse(market, &json)
+    }
+}
+
+#[async_trait]
+impl WallpaperSource for Bing {
+    async fn list(&self, offset: usize, limit: usize) -> Result<Vec<RawImage>, failure::Error> {
+        Ok(self
+            .archive()
+            .await?
+            .into_iter()
+            .skip(offset)
+            .take(limit)
+            .collect())
+    }
+
+    async fn by_id(&self, id_list: &[String]) -> Result<Vec<RawImage>, failure::Error> {
+        let archive = self.archive().await?;
+        Ok(id_list
+            .iter()
+            .filter_map(|id| archive.iter().find(|img| &img.object_id == id))
+            .cloned()
+            .collect())
+    }
+
+    async fn random(&self) -> Result<RawImage, failure::Error> {
+        let mut archive = self.archive().await?;
+        if archive.is_empty() {
+            return Err(format_err!("Bing returned no wallpapers"));
+        }
+        let n = rand::thread_rng().gen_range(0..archive.len());
+        Ok(archive.swap_remove(n))
+    }
+
+    fn image_url(&self, urlbase: &str, resolution: &str) -> String {
+        format!("{}{}_{}.jpg", CDN_BASE, urlbase, resolution)
+    }
+}
+
+#[derive(Deserialize)]
+struct Archive {
+    images: Vec<ArchiveImage>,
+}
+
+#[derive(Deserialize)]
+struct ArchiveImage {
+    startdate: String,
+    urlbase: String,
+    copyright: String,
+    /// Not in old responses
+    #[serde(default)]
+    title: String,
+    #[serde(default)]
+    wp: bool,
+}
+
+/// Parse a response of `HPImageArchive.aspx?format=js` from `market`,
+/// every image has only the meta of this market
+pub fn parse(market: &str, json: &[u8]) -> Result<Vec<RawImage>, failure::Error> {
+    let archive: Archive = serde_json::from_slice(json)?;
+    archive
+        .images
+        .into_iter()
+        .map(|img| -> Result<RawImage, failure::Error> {
+            let id = image_id(&img.urlbase)
+                .ok_or_else(|| format_err!("Unknown urlbase: {}", img.urlbase))?;
+            let date = NaiveDate::parse_from_str(&img.startdate, "%Y%m%d")?;
+            let name = if img.title.is_empty() {
+                id.clone()
+            } else {
+                img.title
+            };
+            Ok(RawImage {
+                name,
+                urlbase: img.urlbase,
+                copyright: img.copyright.clone(),
+                object_id: id.clone(),
+                wp: img.wp,
+                created_at: Some(DateTime::from_utc(date.and_hms(0, 0, 0), Utc)),
+                metas: vec![ImageMeta {
+                    info: img.copyright,
+                    market: market.to_owned(),
+                    image: ImagePointer { object_id: id },
+                }],
+                source: SourceKind::Bing,
+            })
+        })
+        .collect()
+}
+
+/// Merge the same image from different markets, in the order of `pages`,
+/// then sort them by date, newest first
+pub fn merge(pages: Vec<Vec<RawImage>>) -> Vec<RawImage> {
+    let mut images: Vec<RawImage> = Vec::new();
+    for img in pages.into_iter().flatten() {
+        match images.iter_mut().find(|v| v.object_id == img.object_id) {
+            Some(v) => {
+                for meta in img.metas {
+                    if v.metas.iter().all(|m| m.market != meta.market) {
+                        v.metas.push(meta);
+                    }
+                }
+            }
+            None => images.push(img),
+        }
+    }
+    images.sort_by(|a, b| b.created_at.cmp(&a.created_at));
+    images
+}
+
+/// `/th?id=OHR.Name_EN-US123` or `/az/hprichbg/rb/Name_EN-US123` => `Name`
+fn image_id(urlbase: &str) -> Option<String> {
+    let name = urlbase.rsplit(|c| c == '/' || c == '=').next()?;
+    let name = name.trim_start_matches("OHR.");
+    let id = name.split('_').next()?;
+    if id.is_empty() {
+        None
+    } else {
+        Some(id.to_owned())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EN_US: &[u8] = include_bytes!("testdata/bing_en-US.json");
+    const ZH_CN: &[u8] = include_bytes!("testdata/bing_zh-CN.json");
+    /// Before 2019, urlbases were under `/az/hprichbg`, and there were no titles
+    const OLD: &[u8] = include_bytes!("testdata/bing_old.json");
+
+    #[test]
+    fn parse_archive() {
+        let images = parse("en-US", EN_US).unwrap();
+        assert_eq!(images.len(), 2);
+        let fuji = &images[0];
+        assert_eq!(fuji.object_id, "MountFuji");
+        assert_eq!(fuji.name, "A mountain of many moods");
+        assert_eq!(fuji.urlbase, "/th?id=OHR.MountFuji_EN-US5634257385");
+        assert!(fuji.wp);
+        assert_eq!(fuji.created_at, Some(Utc.ymd(2021, 9, 21).and_hms(0, 0, 0)));
+        assert_eq!(fuji.metas.len(), 1);
+        assert_eq!(fuji.metas[0].market, "en-US");
+        assert_eq!(fuji.metas[0].image.object_id, "MountFuji");
+        assert!(!images[1].wp);
+    }
+
+    #[test]
+    fn parse_old_archive() {
+        let images = parse("en-US", OLD).unwrap();
+        assert_eq!(images.len(), 1);
+        assert_eq!(images[0].object_id, "SnowyOwl");
+        // No title, the ID is the best there is
+        assert_eq!(images[0].name, "SnowyOwl");
+        assert_eq!(
+            images[0].urlbase,
+            "/az/hprichbg/rb/SnowyOwl_EN-US9853185473"
+        );
+    }
+
+    #[test]
+    fn parse_broken() {
+        assert!(parse("en-US", b"<html></html>").is_err());
+    }
+
+    #[test]
+    fn merge_markets() {
+        let en_us = parse("en-US", EN_US).unwrap();
+        let zh_cn = parse("zh-CN", ZH_CN).unwrap();
+        let old = parse("en-US", OLD).unwrap();
+        // The same page twice, like the overlapping pages
+        let images = merge(vec![en_us.clone(), zh_cn, old, en_us]);
+        let ids: Vec<&str> = images.iter().map(|img| &*img.object_id).collect();
+        assert_eq!(ids, ["MountFuji", "AutumnMoon", "SnowyOwl"]);
+        let markets: Vec<&str> = images[0].metas.iter().map(|m| &*m.market).collect();
+        assert_eq!(markets, ["en-US", "zh-CN"]);
+        // Meta of the first market is kept
+        assert_eq!(images[0].name, "A mountain of many moods");
+        assert_eq!(images[1].metas.len(), 1);
+    }
+
+    #[test]
+    fn image_ids() {
+        assert_eq!(
+            image_id("/th?id=OHR.MountFuji_EN-US5634257385").as_deref(),
+            Some("MountFuji")
+        );
+        assert_eq!(
+            image_id("/az/hprichbg/rb/SnowyOwl_EN-US9853185473").as_deref(),
+            Some("SnowyOwl")
+        );
+        assert_eq!(image_id("/th?id=OHR._EN-US123"), None);
+        assert_eq!(image_id(""), None);
+    }
+}
@@ -0,0 +1,187 @@
+//! Every image got from remote sources is kept in `cache_dir`, one JSON per line,
+//! so the list, favorites and auto-change still work offline
+
+use std::fs::{self, File, OpenOptions};
+use std::io::{prelude::*, BufReader, BufWriter};
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use super::{ImageMeta, RawImage, SourceKind};
+
+const CACHE_FILE: &str = "metadata.jsonl";
+
+#[derive(Serialize, Deserialize)]
+struct Record {
+    source: SourceKind,
+    #[serde(flatten)]
+    image: RawImage,
+    metas: Vec<ImageMeta>,
+}
+
+impl From<&RawImage> for Record {
+    fn from(image: &RawImage) -> Record {
+        Record {
+            source: image.source,
+            image: image.clone(),
+            metas: image.metas.clone(),
+        }
+    }
+}
+
+impl From<Record> for RawImage {
+    fn from(record: Record) -> RawImage {
+        RawImage {
+            metas: record.metas,
+            source: record.source,
+            ..record.image
+        }
+    }
+}
+
+pub struct MetaCache {
+    path: PathBuf,
+    /// Newest first
+    images: Vec<RawImage>,
+}
+
+impl MetaCache {
+    /// Broken lines are skipped, a later line overrides earlier ones of the same image
+    pub fn open(cache_dir: &Path) -> Self {
+        let path = cache_dir.join(CACHE_FILE);
+        let mut cache = MetaCache {
+            path,
+            images: Vec::new(),
+        };
+        let mut lines = 0;
+        if let Ok(file) = File::open(&cache.path) {
+            for line in BufReader::new(file).lines() {
+                let record = line
+                    .ok()
+                    .and_then(|l| serde_json::from_str::<Record>(&l).ok());
+                if let Some(record) = record {
+                    cache.update(record.into());
+                    lines += 1;
+                }
+            }
+        }
+        cache.sort();
+        // Outdated lines are more than the latest ones
+        if lines > cache.images.len() * 2 {
+            cache
+                .compact()
+                .unwrap_or_else(|e| eprintln!("Failed to compact cache: {}", e));
+        }
+        cache
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Remember new or changed images, and write them to disk
+    pub fn insert(&mut self, images: &[RawImage]) -> Result<(), failure::Error> {
+        let changed: Vec<&RawImage> = images
+            .iter()
+            .filter(|img| self.get(img.source, &img.object_id) != Some(*img))
+            .collect();
+        if changed.is_empty() {
+            return Ok(());
+        }
+
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        let mut file = BufWriter::new(file);
+        for img in &changed {
+            serde_json::to_writer(&mut file, &Record::from(*img))?;
+            file.write_all(b"\n")?;
+        }
+        file.flush()?;
+
+        for img in changed {
+            self.update(img.clone());
+        }
+        self.sort();
+        Ok(())
+    }
+
+    pub fn get(&self, source: SourceKind, id: &str) -> Option<&RawImage> {
+        self.images
+            .iter()
+            .find(|img| img.source == source && img.object_id == id)
+    }
+
+    /// Like `WallpaperSource::list`, `None` if nothing of `source` is cached
+    pub fn list(&self, source: SourceKind, offset: usize, limit: usize) -> Option<Vec<RawImage>> {
+        let images: Vec<&RawImage> = self
+            .images
+            .iter()
+            .filter(|img| img.source == source)
+            .collect();
+        if images.is_empty() {
+            return None;
+        }
+        Some(
+            images
+                .into_iter()
+                .skip(offset)
+                .take(limit)
+                .cloned()
+                .collect(),
+        )
+    }
+
+    /// Like `WallpaperSource::by_id`, images of any source
+    pub fn by_id(&self, id_list: &[String]) -> Vec<RawImage> {
+        id_list
+            .iter()
+            .filter_map(|id| self.images.iter().find(|img| &img.object_id == id))
+            .cloned()
+            .collect()
+    }
+
+    pub fn random(&self, source: SourceKind) -> Option<RawImage> {
+        let images: Vec<&RawImage> = self
+            .images
+            .iter()
+            .filter(|img| img.source == source)
+            .collect();
+        if images.is_empty() {
+            return None;
+        }
+        Some(images[rand::random::<usize>() % images.len()].clone())
+    }
+
+    fn update(&mut self, img: RawImage) {
+        match self
+            .images
+            .iter_mut()
+            .find(|v| v.source == img.source && v.object_id == img.object_id)
+        {
+            Some(v) => *v = img,
+            None => self.images.push(img),
+        }
+    }
+
+    fn sort(&mut self) {
+        self.images.sort_by(|a, b| b.created_at.cmp(&a.created_at));
+    }
+
+    /// Rewrite the file with only the latest lines
+    fn compact(&self) -> Result<(), failure::Error> {
+        let tmp = self.path.with_extension("jsonl.tmp");
+        let mut file = BufWriter::new(File::create(&tmp)?);
+        for img in &self.images {
+            serde_json::to_writer(&mut file, &Record::from(img))?;
+            file.write_all(b"\n")?;
+        }
+        file.flush()?;
+        fs::rename(&tmp, &self.path)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,230 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::ops::{ControlFlow, FromResidual, Try};
+use std::sync::RwLock;
+
+use async_trait::async_trait;
+use failure::{self, Fail};
+use rand::{rngs::SmallRng, Rng, SeedableRng};
+use reqwest;
+use serde::Deserialize;
+
+use super::{ImageMeta, RawImage, WallpaperSource};
+use crate::error::Error;
+use crate::http;
+
+const API_BASE: &str = "https://leanapi.bohan.co";
+const CDN_BASE: &str = "https://wpdn.bohan.co";
+#[cfg(not(test))]
+const AVOS_ID: &str = env!("AVOS_ID");
+#[cfg(not(test))]
+const AVOS_KEY: &str = env!("AVOS_KEY");
+#[cfg(test)]
+const AVOS_ID: &str = "";
+#[cfg(test)]
+const AVOS_KEY: &str = "";
+
+/// bohan's mirror, or another deployment of it
+pub struct LeanCloud {
+    api_base: RwLock<String>,
+    cdn_base: RwLock<String>,
+    /// Number of wallpapers on `api_base`, asked once
+    wp_count: RwLock<Option<usize>>,
+}
+
+impl LeanCloud {
+    pub fn new() -> Self {
+        LeanCloud {
+            api_base: RwLock::new(API_BASE.to_owned()),
+            cdn_base: RwLock::new(CDN_BASE.to_owned()),
+            wp_count: RwLock::new(None),
+        }
+    }
+
+    /// Empty ones are reset to bohan's
+    pub fn set_endpoints(&self, api_base: &str, cdn_base: &str) {
+        let or_default = |base: &str, default: &str| {
+            let base = base.trim().trim_end_matches('/');
+            if base.is_empty() {
+                default.to_owned()
+            } else {
+                base.to_owned()
+            }
+        };
+        let api_base = or_default(api_base, API_BASE);
+        let mut old_api_base = self.api_base.write().unwrap();
+        if *old_api_base != api_base {
+            *old_api_base = api_base;
+            *self.wp_count.write().unwrap() = None;
+        }
+        *self.cdn_base.write().unwrap() = or_default(cdn_base, CDN_BASE);
+    }
+
+    fn url(&self, class: &str, params: &[(&str, &str)]) -> Result<reqwest::Url, failure::Error> {
+        let url = format!("{}/1.1/classes/{}", self.api_base.read().unwrap(), class);
+        Ok(reqwest::Url::parse_with_params(&url, params)?)
+    }
+
+    fn get(&self, url: reqwest::Url) -> reqwest::RequestBuilder {
+        http::client()
+            .get(url)
+            .header("X-AVOSCloud-Application-Id", AVOS_ID)
+            .header("X-AVOSCloud-Application-Key", AVOS_KEY)
+    }
+
+    async fn fill_metadata(
+        &self,
+        mut images: Vec<RawImage>,
+    ) -> Result<Vec<RawImage>, failure::Error> {
+        let where_query: Vec<String> = images
+            .iter()
+            .map(|img| {
+                format!(
+                    r#"{{"image":{{"__type":"Pointer","className":"Image","objectId":"{}"}}}}"#,
+                    img.object_id
+                )
+            })
+            .collect();
+        let where_query = format!("{{\"$or\":[{}]}}", where_query.join(","));
+
+        // Default limit is 100, maximum is 1000
+        let url = self.url("Archive", &[("where", &*where_query), ("limit", "1000")])?;
+
+        let resp: Response<ImageMeta> = http::send(self.get(url)).await?.json().await?;
+        let metas = resp?;
+
+        for meta in metas {
+            for img in &mut images {
+                if meta.image.object_id == img.object_id {
+                    img.metas.push(meta);
+                    break;
+                }
+            }
+        }
+
+        Ok(images)
+    }
+}
+
+#[async_trait]
+impl WallpaperSource for LeanCloud {
+    async fn list(&self, offset: usize, limit: usize) -> Result<Vec<RawImage>, failure::Error> {
+        let offset = offset.to_string();
+        let limit = limit.to_string();
+        let url = self.url(
+            "Image",
+            &[
+                ("order", "-createdAt"),
+                ("skip", &offset),
+                ("limit", &limit),
+            ],
+        )?;
+
+        let resp: Response<RawImage> = http::send(self.get(url)).await?.json().await?;
+        let images = resp?;
+
+        self.fill_metadata(images).await
+    }
+
+    async fn by_id(&self, id_list: &[String]) -> Result<Vec<RawImage>, failure::Error> {
+        let where_query: Vec<String> = id_list
+            .iter()
+            .map(|id| format!(r#"{{"objectId":"{}"}}"#, id))
+            .collect();
+        let where_query = format!("{{\"$or\":[{}]}}", where_query.join(","));
+
+        let url = self.url("Image", &[("where", &where_query)])?;
+
+        let resp: Response<RawImage> = http::send(self.get(url)).await?.json().await?;
+        let mut images = resp?;
+        // Keep the order
+        let id_index: HashMap<&str, usize> = id_list
+            .iter()
+            .enumerate()
+            .map(|(index, id)| (id.as_str(), index))
+            .collect();
+        images.sort_by_key(|img| id_index.get(&*img.object_id));
+
+        self.fill_metadata(images).await
+    }
+
+    async fn random(&self) -> Result<RawImage, failure::Error> {
+        thread_local! {
+            static SMALL_RNG: RefCell<SmallRng> = RefCell::new(SmallRng::from_entropy());
+        }
+
+        let cached_count = *self.wp_count.read().unwrap();
+        let wp_count = if let Some(count) = cached_count {
+            count
+        } else {
+            let url = self.url("Image", &[("count", "1")])?;
+            #[derive(Deserialize)]
+            struct Resp {
+                count: usize,
+            }
+            let resp: Resp = http::send(self.get(url)).await?.json().await?;
+            *self.wp_count.write().unwrap() = Some(resp.count);
+            resp.count
+        };
+
+        if wp_count == 0 {
+            return Err(Error::Server("No wallpapers".to_owned()).into());
+        }
+        let n = SMALL_RNG.with(|rng| rng.borrow_mut().gen::<usize>()) % wp_count;
+
+        let url = self.url("Image", &[("limit", "1"), ("skip", &n.to_string())])?;
+
+        let resp: Response<RawImage> = http::send(self.get(url)).await?.json().await?;
+        let mut images = resp?;
+
+        images
+            .pop()
+            .ok_or_else(|| Error::Server(format!("No wallpaper at {}", n)).into())
+    }
+
+    fn image_url(&self, urlbase: &str, resolution: &str) -> String {
+        let cdn_base = self.cdn_base.read().unwrap();
+        format!("{}{}_{}.jpg", cdn_base, urlbase, resolution)
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Response<T> {
+    Ok { results: Vec<T> },
+    Err { code: i32, error: String },
+}
+
+impl<T> Try for Response<T> {
+    type Output = Vec<T>;
+    type Residual = Result<Infallible, ServerError>;
+    fn branch(self) -> ControlFlow<Self::Residual, <Response<T> as Try>::Output> {
+        match self {
+            Response::Ok { results } => ControlFlow::Continue(results),
+            Response::Err { code, error } => ControlFlow::Break(Err(ServerError { code, error })),
+        }
+    }
+    fn from_output(v: <Response<T> as Try>::Output) -> Self {
+        Response::Ok { results: v }
+    }
+}
+
+impl<T> FromResidual for Response<T> {
+    fn from_residual(residual: <Self as Try>::Residual) -> Self {
+        match residual {
+            Ok(_) => unreachable!(),
+            Err(e) => Self::Err {
+                code: e.code,
+                error: e.error,
+            },
+        }
+    }
+}
+
+#[derive(Fail, Debug)]
+#[fail(display = "Server Error: {}", error)]
+pub struct ServerError {
+    code: i32,
+    error: String,
+}
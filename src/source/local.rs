@@ -0,0 +1,111 @@
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::RwLock;
+
+use async_trait::async_trait;
+use chrono::prelude::*;
+use failure::{self, format_err};
+use rand::Rng;
+
+use super::{RawImage, SourceKind, WallpaperSource};
+
+/// IDs of local images are their file names with this prefix,
+/// so they never collide with remote ones
+pub const ID_PREFIX: &str = "local:";
+const EXTENSIONS: &[&str] = &["jpg", "jpeg", "png", "webp", "bmp"];
+
+/// Images in a folder of the user, `urlbase` is the full path
+pub struct Local {
+    dir: RwLock<PathBuf>,
+}
+
+impl Local {
+    pub fn new() -> Self {
+        Local {
+            dir: RwLock::default(),
+        }
+    }
+
+    pub fn set_dir(&self, dir: &Path) {
+        let dir = match (dir.strip_prefix("~"), env::var_os("HOME")) {
+            (Ok(path), Some(home)) => PathBuf::from(home).join(path),
+            _ => dir.to_owned(),
+        };
+        *self.dir.write().unwrap() = dir;
+    }
+
+    /// All images in the folder, newest first
+    fn scan(&self) -> Result<Vec<RawImage>, failure::Error> {
+        let dir = self.dir.read().unwrap().clone();
+        if dir.as_os_str().is_empty() {
+            return Ok(Vec::new());
+        }
+        let mut images = Vec::new();
+        for entry in fs::read_dir(&dir)? {
+            if let Some(img) = image(&entry?.path()) {
+                images.push(img);
+            }
+        }
+        images.sort_by(|a, b| b.created_at.cmp(&a.created_at));
+        Ok(images)
+    }
+}
+
+#[async_trait]
+impl WallpaperSource for Local {
+    async fn list(&self, offset: usize, limit: usize) -> Result<Vec<RawImage>, failure::Error> {
+        Ok(self.scan()?.into_iter().skip(offset).take(limit).collect())
+    }
+
+    async fn by_id(&self, id_list: &[String]) -> Result<Vec<RawImage>, failure::Error> {
+        let dir = self.dir.read().unwrap().clone();
+        Ok(id_list
+            .iter()
+            .filter_map(|id| id.strip_prefix(ID_PREFIX))
+            // Only the folder itself
+            .filter(|name| !name.contains('/'))
+            .filter_map(|name| image(&dir.join(name)))
+            .collect())
+    }
+
+    async fn random(&self) -> Result<RawImage, failure::Error> {
+        let mut images = self.scan()?;
+        if images.is_empty() {
+            return Err(format_err!("No images in the local folder"));
+        }
+        let n = rand::thread_rng().gen_range(0..images.len());
+        Ok(images.swap_remove(n))
+    }
+
+    fn image_url(&self, urlbase: &str, _resolution: &str) -> String {
+        // Escaped, `#` or `?` in file names are not a fragment or a query
+        reqwest::Url::from_file_path(urlbase)
+            .map(String::from)
+            .unwrap_or_else(|_| format!("file://{}", urlbase))
+    }
+
+    fn local_file(&self, urlbase: &str) -> Option<PathBuf> {
+        Some(urlbase.into())
+    }
+}
+
+fn image(path: &Path) -> Option<RawImage> {
+    let extension = path.extension()?.to_str()?.to_lowercase();
+    if !EXTENSIONS.contains(&&*extension) {
+        return None;
+    }
+    let metadata = fs::metadata(path).ok()?;
+    if !metadata.is_file() {
+        return None;
+    }
+    let name = path.file_name()?.to_str()?;
+    Some(RawImage {
+        name: path.file_stem()?.to_string_lossy().into(),
+        urlbase: path.to_str()?.to_owned(),
+        object_id: format!("{}{}", ID_PREFIX, name),
+        created_at: metadata.modified().ok().map(DateTime::<Utc>::from),
+        source: SourceKind::Local,
+        ..RawImage::default()
+    })
+}
@@ -0,0 +1,328 @@
+//! Where wallpapers come from
+
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::slice;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+
+use async_trait::async_trait;
+use chrono::prelude::*;
+use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
+
+use crate::config::Snapshot;
+use crate::http;
+
+mod bing;
+mod cache;
+mod leancloud;
+mod local;
+
+use bing::Bing;
+use cache::MetaCache;
+use leancloud::LeanCloud;
+pub use leancloud::ServerError;
+use local::Local;
+pub use local::ID_PREFIX as LOCAL_ID_PREFIX;
+
+lazy_static! {
+    static ref LEANCLOUD: LeanCloud = LeanCloud::new();
+    static ref BING: Bing = Bing::new();
+    static ref LOCAL: Local = Local::new();
+    static ref CACHE: Mutex<Option<MetaCache>> = Mutex::default();
+}
+
+#[cfg(test)]
+lazy_static! {
+    /// Sources replaced by tests with `set_fake`
+    static ref FAKES: Mutex<HashMap<SourceKind, &'static dyn WallpaperSource>> = Mutex::default();
+}
+
+/// The last request failed, results are from the cache
+static OFFLINE: AtomicBool = AtomicBool::new(false);
+
+#[async_trait]
+pub trait WallpaperSource: Send + Sync {
+    /// Newest first
+    async fn list(&self, offset: usize, limit: usize) -> Result<Vec<RawImage>, failure::Error>;
+    /// Keep the order of `id_list`, unknown IDs are skipped
+    async fn by_id(&self, id_list: &[String]) -> Result<Vec<RawImage>, failure::Error>;
+    async fn random(&self) -> Result<RawImage, failure::Error>;
+    /// URL of the image in `resolution`, like `1920x1080`
+    fn image_url(&self, urlbase: &str, resolution: &str) -> String;
+    /// The image is already on disk, nothing to download
+    fn local_file(&self, _urlbase: &str) -> Option<PathBuf> {
+        None
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Hash, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum SourceKind {
+    LeanCloud,
+    Bing,
+    Local,
+}
+
+impl SourceKind {
+    /// In the order of the settings page
+    pub const ALL: &'static [SourceKind] =
+        &[SourceKind::LeanCloud, SourceKind::Bing, SourceKind::Local];
+}
+
+impl Default for SourceKind {
+    fn default() -> Self {
+        SourceKind::LeanCloud
+    }
+}
+
+pub fn get(kind: SourceKind) -> &'static dyn WallpaperSource {
+    #[cfg(test)]
+    {
+        if let Some(fake) = FAKES.lock().unwrap().get(&kind) {
+            return *fake;
+        }
+    }
+    match kind {
+        SourceKind::LeanCloud => &*LEANCLOUD,
+        SourceKind::Bing => &*BING,
+        SourceKind::Local => &*LOCAL,
+    }
+}
+
+/// Use `source` for `kind` from now on
+#[cfg(test)]
+pub fn set_fake(kind: SourceKind, source: impl WallpaperSource + 'static) {
+    let source: &'static dyn WallpaperSource = Box::leak(Box::new(source));
+    FAKES.lock().unwrap().insert(kind, source);
+}
+
+/// Apply the settings of sources, and the proxy they are reached through
+pub fn configure(config: &Snapshot) {
+    http::set_proxy(&config.proxy);
+    LEANCLOUD.set_endpoints(&config.api_base, &config.cdn_base);
+    LOCAL.set_dir(&config.local_dir);
+    let mut cache = CACHE.lock().unwrap();
+    if cache.as_ref().and_then(|c| c.path().parent()) != Some(&*config.cache_dir) {
+        *cache = Some(MetaCache::open(&config.cache_dir));
+    }
+}
+
+/// The last request failed, so results are from the cache
+pub fn offline() -> bool {
+    OFFLINE.load(Ordering::Relaxed)
+}
+
+/// A cached image of any source
+pub fn cached(id: &str) -> Option<RawImage> {
+    let cache = CACHE.lock().unwrap();
+    cache.as_ref()?.by_id(&[id.to_owned()]).pop()
+}
+
+/// Like `WallpaperSource::list` of `kind`, from the cache if the request fails
+pub async fn list(
+    kind: SourceKind,
+    offset: usize,
+    limit: usize,
+) -> Result<Vec<RawImage>, failure::Error> {
+    if kind == SourceKind::Local {
+        return get(kind).list(offset, limit).await;
+    }
+    let r = get(kind).list(offset, limit).await;
+    with_cache(r, Vec::as_slice, |cache| cache.list(kind, offset, limit))
+}
+
+/// Like `WallpaperSource::random` of `kind`, from the cache if the request fails
+pub async fn random(kind: SourceKind) -> Result<RawImage, failure::Error> {
+    if kind == SourceKind::Local {
+        return get(kind).random().await;
+    }
+    let r = get(kind).random().await;
+    with_cache(r, slice::from_ref, |cache| cache.random(kind))
+}
+
+/// Like `WallpaperSource::by_id` of `kind`, but local images are always found,
+/// so favorites from the local folder work with any source
+pub async fn by_id(kind: SourceKind, id_list: &[String]) -> Result<Vec<RawImage>, failure::Error> {
+    let (local, remote): (Vec<String>, Vec<String>) = id_list
+        .iter()
+        .cloned()
+        .partition(|id| id.starts_with(LOCAL_ID_PREFIX));
+    let mut images = Vec::new();
+    if !local.is_empty() {
+        images.extend(get(SourceKind::Local).by_id(&local).await?);
+    }
+    if !remote.is_empty() {
+        if kind != SourceKind::Local {
+            let r = get(kind).by_id(&remote).await;
+            let from_cache =
+                |cache: &MetaCache| Some(cache.by_id(&remote)).filter(|v| !v.is_empty());
+            images.extend(with_cache(r, Vec::as_slice, from_cache)?);
+        }
+        // Sources like Bing forget old images, but the cache still knows them
+        let missing: Vec<String> = remote
+            .into_iter()
+            .filter(|id| images.iter().all(|img| &img.object_id != id))
+            .collect();
+        if let Some(cache) = &*CACHE.lock().unwrap() {
+            images.extend(cache.by_id(&missing));
+        }
+    }
+    // Keep the order
+    let id_index: HashMap<&str, usize> = id_list
+        .iter()
+        .enumerate()
+        .map(|(index, id)| (id.as_str(), index))
+        .collect();
+    images.sort_by_key(|img| id_index.get(&*img.object_id));
+    Ok(images)
+}
+
+/// Remember the images got from a remote source,
+/// or use the cache instead if the request failed
+fn with_cache<T>(
+    r: Result<T, failure::Error>,
+    images: impl FnOnce(&T) -> &[RawImage],
+    fallback: impl FnOnce(&MetaCache) -> Option<T>,
+) -> Result<T, failure::Error> {
+    let mut cache = CACHE.lock().unwrap();
+    OFFLINE.store(r.is_err(), Ordering::Relaxed);
+    match (r, &mut *cache) {
+        (Ok(v), Some(cache)) => {
+            if let Err(e) = cache.insert(images(&v)) {
+                eprintln!("Failed to cache wallpapers: {}", e);
+            }
+            Ok(v)
+        }
+        (Err(e), Some(cache)) => fallback(cache).ok_or(e),
+        (r, None) => r,
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Default, PartialEq, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ImageMeta {
+    pub info: String,
+    pub market: String,
+    #[serde(default)]
+    pub image: ImagePointer,
+}
+
+#[derive(Serialize, Deserialize, Clone, Default, PartialEq, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ImagePointer {
+    pub object_id: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Default, PartialEq, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct RawImage {
+    pub name: String,
+    pub urlbase: String,
+    pub copyright: String,
+    pub object_id: String,
+    pub wp: bool,
+    pub created_at: Option<DateTime<Utc>>,
+    #[serde(skip)]
+    pub metas: Vec<ImageMeta>,
+    #[serde(skip)]
+    pub source: SourceKind,
+}
+
+impl RawImage {
+    pub fn source(&self) -> &'static dyn WallpaperSource {
+        get(self.source)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Images in memory, `by_id` answers in reverse to check the order is restored
+    struct Fake(Vec<RawImage>);
+
+    #[async_trait]
+    impl WallpaperSource for Fake {
+        async fn list(&self, offset: usize, limit: usize) -> Result<Vec<RawImage>, failure::Error> {
+            Ok(self.0.iter().skip(offset).take(limit).cloned().collect())
+        }
+
+        async fn by_id(&self, id_list: &[String]) -> Result<Vec<RawImage>, failure::Error> {
+            Ok(self
+                .0
+                .iter()
+                .rev()
+                .filter(|img| id_list.contains(&img.object_id))
+                .cloned()
+                .collect())
+        }
+
+        async fn random(&self) -> Result<RawImage, failure::Error> {
+            self.0
+                .first()
+                .cloned()
+                .ok_or_else(|| failure::format_err!("No wallpapers"))
+        }
+
+        fn image_url(&self, urlbase: &str, resolution: &str) -> String {
+            format!("fake:{}_{}", urlbase, resolution)
+        }
+    }
+
+    fn image(id: &str, source: SourceKind) -> RawImage {
+        RawImage {
+            object_id: id.to_owned(),
+            urlbase: id.to_owned(),
+            source,
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn uses_the_fake_source() {
+        let images = vec![image("a", SourceKind::Bing), image("b", SourceKind::Bing)];
+        set_fake(SourceKind::Bing, Fake(images));
+
+        let listed = list(SourceKind::Bing, 1, 10).await.unwrap();
+        assert_eq!(listed.len(), 1);
+        assert_eq!(listed[0].object_id, "b");
+        assert_eq!(random(SourceKind::Bing).await.unwrap().object_id, "a");
+        assert_eq!(
+            listed[0].source().image_url("b", "1920x1080"),
+            "fake:b_1920x1080"
+        );
+    }
+
+    #[tokio::test]
+    async fn by_id_keeps_the_order() {
+        let local_a = format!("{}a.jpg", LOCAL_ID_PREFIX);
+        let local_b = format!("{}b.jpg", LOCAL_ID_PREFIX);
+        set_fake(
+            SourceKind::Local,
+            Fake(vec![
+                image(&local_a, SourceKind::Local),
+                image(&local_b, SourceKind::Local),
+            ]),
+        );
+        set_fake(
+            SourceKind::LeanCloud,
+            Fake(vec![
+                image("x", SourceKind::LeanCloud),
+                image("y", SourceKind::LeanCloud),
+            ]),
+        );
+
+        let ids = vec![
+            "y".to_owned(),
+            local_a.clone(),
+            "unknown".to_owned(),
+            "x".to_owned(),
+            local_b.clone(),
+        ];
+        let found = by_id(SourceKind::LeanCloud, &ids).await.unwrap();
+        let found: Vec<&str> = found.iter().map(|img| &*img.object_id).collect();
+        assert_eq!(found, ["y", &*local_a, "x", &*local_b]);
+    }
+}
@@ -1,20 +1,79 @@
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{mpsc, Mutex};
 
 use image::ImageFormat;
 use ksni;
 use qmetaobject::*;
 
+use crate::async_utils;
+use crate::config::Snapshot;
+use crate::implementation::{self, CurrentWallpaper};
+use crate::scheduler;
+
+const MAX_RECENT_WALLPAPERS: usize = 5;
+
 lazy_static! {
     /// Send Cmd to TrayProxy
     static ref REMOTE_CMD_SENDER: Mutex<Option<Box<dyn Fn(Cmd) + Send>>> = Mutex::default();
     /// If TrayProxy isn't running, all Cmd will be here
     static ref LOCAL_CMD_RECIVER: Mutex<Option<mpsc::Receiver<Cmd>>> = Mutex::default();
+    static ref LOCAL_CMD_SENDER: Mutex<Option<mpsc::Sender<Cmd>>> = Mutex::default();
 }
 
-#[derive(Debug, Clone, Copy)]
+/// `Qt.quit()` only closes the window, `main` checks this to end the process
+static QUIT_REQUESTED: AtomicBool = AtomicBool::new(false);
+
+#[derive(Debug, Clone)]
 pub enum Cmd {
     Open,
     Quit,
+    Next,
+    SetById(String),
+    ToggleLike,
+    Pause(bool),
+    SetMode(u8),
+    ImportFavorites(String),
+    /// A key of the config and its value, see `Config::with_value`
+    SetConfig(String, String),
+}
+
+/// Handle a command from the tray, or from other processes
+pub fn emit_cmd(cmd: Cmd) {
+    match cmd {
+        Cmd::Next => scheduler::next(),
+        Cmd::SetById(id) => scheduler::set_by_id(id),
+        // Config is owned by the window if it's opened
+        Cmd::Open
+        | Cmd::Quit
+        | Cmd::ToggleLike
+        | Cmd::Pause(_)
+        | Cmd::SetMode(_)
+        | Cmd::ImportFavorites(_)
+        | Cmd::SetConfig(..) => emit_window_cmd(cmd),
+    }
+}
+
+fn emit_window_cmd(cmd: Cmd) {
+    if let Cmd::Quit = cmd {
+        QUIT_REQUESTED.store(true, Ordering::SeqCst);
+    }
+    let tray_proxy_exist = REMOTE_CMD_SENDER
+        .lock()
+        .unwrap()
+        .as_ref()
+        .map(|emit| (emit)(cmd.clone()))
+        .is_some();
+    if !tray_proxy_exist {
+        if let Some(cmd_tx) = &*LOCAL_CMD_SENDER.lock().unwrap() {
+            cmd_tx.send(cmd).unwrap()
+        }
+    }
+}
+
+/// The tray or another process asked to quit
+pub fn quit_requested() -> bool {
+    QUIT_REQUESTED.load(Ordering::SeqCst)
 }
 
 pub fn wait() -> Cmd {
@@ -31,6 +90,11 @@ pub struct TrayProxy {
     pub connect_to_backend: qt_method!(fn(&mut self)),
     pub open: qt_signal!(),
     pub quit: qt_signal!(),
+    pub toggle_like: qt_signal!(),
+    pub pause: qt_signal!(paused: bool),
+    pub set_mode: qt_signal!(mode: u8),
+    pub import_favorites: qt_signal!(path: QString),
+    pub set_config: qt_signal!(key: QString, value: QString),
 }
 
 impl TrayProxy {
@@ -43,6 +107,12 @@ impl TrayProxy {
             match cmd {
                 Cmd::Open => this.open(),
                 Cmd::Quit => this.quit(),
+                Cmd::ToggleLike => this.toggle_like(),
+                Cmd::Pause(paused) => this.pause(paused),
+                Cmd::SetMode(mode) => this.set_mode(mode),
+                Cmd::ImportFavorites(path) => this.import_favorites(path.into()),
+                Cmd::SetConfig(key, value) => this.set_config(key.into(), value.into()),
+                _ => unreachable!("only window commands are sent to TrayProxy"),
             }
         });
         *REMOTE_CMD_SENDER.lock().unwrap() = Some(Box::new(on_open_callback));
@@ -55,21 +125,29 @@ impl Drop for TrayProxy {
     }
 }
 
+#[derive(Default)]
 pub struct Tray {
-    cmd_tx: mpsc::Sender<Cmd>,
+    config: Option<Snapshot>,
+    current: Option<CurrentWallpaper>,
+    recent: VecDeque<CurrentWallpaper>,
 }
 
 impl Tray {
-    fn emit_cmd(&mut self, cmd: Cmd) {
-        let tray_proxy_exist = REMOTE_CMD_SENDER
-            .lock()
-            .unwrap()
-            .as_ref()
-            .map(|emit| (emit)(cmd))
-            .is_some();
-        if !tray_proxy_exist {
-            self.cmd_tx.send(cmd).unwrap()
+    fn wallpaper_changed(&mut self, current: &CurrentWallpaper) {
+        self.recent.retain(|wp| wp.id != current.id);
+        self.recent.push_front(current.clone());
+        self.recent.truncate(MAX_RECENT_WALLPAPERS);
+        self.current = Some(current.clone());
+    }
+
+    fn config_changed(&mut self, config: Option<Snapshot>) {
+        if self.current.is_none() {
+            // Set by the last run
+            self.current = config
+                .as_ref()
+                .and_then(|c| implementation::current_wallpaper(&c.cache_dir));
         }
+        self.config = config;
     }
 }
 
@@ -80,12 +158,122 @@ impl ksni::Tray for Tray {
     fn icon_pixmap(&self) -> Vec<ksni::Icon> {
         icons()
     }
+    fn title(&self) -> String {
+        match &self.current {
+            Some(current) if !current.name.is_empty() => current.name.clone(),
+            _ => "Biying Wallpaper".to_owned(),
+        }
+    }
+    fn tool_tip(&self) -> ksni::ToolTip {
+        let current = match &self.current {
+            Some(current) => current,
+            None => return Default::default(),
+        };
+        let mut description = vec![format!("© {}", current.copyright)];
+        description.extend(
+            current
+                .metas
+                .iter()
+                .map(|meta| format!("{}: {}", meta.market, meta.info)),
+        );
+        ksni::ToolTip {
+            title: current.name.clone(),
+            description: description.join("\n"),
+            ..Default::default()
+        }
+    }
     fn menu(&self) -> Vec<ksni::MenuItem<Self>> {
         use ksni::menu::*;
+        let liked = match (&self.config, &self.current) {
+            (Some(config), Some(current)) => config.favorites.contains(&current.id),
+            _ => false,
+        };
+        let auto_change = self.config.as_ref().map(|c| c.auto_change.clone());
+        let recent = self
+            .recent
+            .iter()
+            .map(|wp| {
+                let id = wp.id.clone();
+                StandardItem {
+                    label: if wp.name.is_empty() {
+                        wp.id.clone()
+                    } else {
+                        wp.name.clone()
+                    },
+                    activate: Box::new(move |_: &mut Self| emit_cmd(Cmd::SetById(id.clone()))),
+                    ..Default::default()
+                }
+                .into()
+            })
+            .collect();
         vec![
             StandardItem {
                 label: "Open".into(),
-                activate: Box::new(|this: &mut Self| this.emit_cmd(Cmd::Open)),
+                activate: Box::new(|_: &mut Self| emit_cmd(Cmd::Open)),
+                ..Default::default()
+            }
+            .into(),
+            StandardItem {
+                label: "Next Wallpaper".into(),
+                icon_name: "go-next".into(),
+                activate: Box::new(|_: &mut Self| emit_cmd(Cmd::Next)),
+                ..Default::default()
+            }
+            .into(),
+            StandardItem {
+                label: if liked {
+                    "Unlike Current"
+                } else {
+                    "Like Current"
+                }
+                .into(),
+                icon_name: "emblem-favorite-symbolic".into(),
+                enabled: self.current.is_some(),
+                activate: Box::new(|_: &mut Self| emit_cmd(Cmd::ToggleLike)),
+                ..Default::default()
+            }
+            .into(),
+            MenuItem::Separator,
+            CheckmarkItem {
+                label: "Pause Auto-Change".into(),
+                enabled: auto_change.is_some(),
+                checked: auto_change.as_ref().map_or(true, |a| !a.enable),
+                activate: Box::new(|this: &mut Self| {
+                    let enabled = this.config.as_ref().map_or(false, |c| c.auto_change.enable);
+                    emit_cmd(Cmd::Pause(enabled))
+                }),
+                ..Default::default()
+            }
+            .into(),
+            SubMenu {
+                label: "Mode".into(),
+                enabled: auto_change.is_some(),
+                submenu: vec![RadioGroup {
+                    selected: auto_change.as_ref().map_or(0, |a| a.mode as usize),
+                    select: Box::new(|_: &mut Self, mode| emit_cmd(Cmd::SetMode(mode as u8))),
+                    options: vec![
+                        RadioItem {
+                            label: "Newest".into(),
+                            ..Default::default()
+                        },
+                        RadioItem {
+                            label: "Favourites".into(),
+                            ..Default::default()
+                        },
+                        RadioItem {
+                            label: "Random".into(),
+                            ..Default::default()
+                        },
+                    ],
+                }
+                .into()],
+                ..Default::default()
+            }
+            .into(),
+            SubMenu {
+                label: "Recent Wallpapers".into(),
+                enabled: !self.recent.is_empty(),
+                submenu: recent,
                 ..Default::default()
             }
             .into(),
@@ -93,7 +281,7 @@ impl ksni::Tray for Tray {
             StandardItem {
                 label: "Quit".into(),
                 icon_name: "application-exit".into(),
-                activate: Box::new(|this: &mut Self| this.emit_cmd(Cmd::Quit)),
+                activate: Box::new(|_: &mut Self| emit_cmd(Cmd::Quit)),
                 ..Default::default()
             }
             .into(),
@@ -106,9 +294,26 @@ pub fn run_tray_in_background() {
     if cmd_rx.is_none() {
         let (sender, recver) = mpsc::channel();
         *cmd_rx = Some(recver);
+        *LOCAL_CMD_SENDER.lock().unwrap() = Some(sender);
 
-        let service = ksni::TrayService::new(Tray { cmd_tx: sender });
+        let service = ksni::TrayService::new(Tray::default());
+        let handle = service.handle();
         service.spawn();
+
+        let tray = handle.clone();
+        implementation::on_wallpaper_changed(move |current| {
+            tray.update(|tray| tray.wallpaper_changed(current))
+        });
+        async_utils::spawn(async move {
+            let mut config = scheduler::subscribe();
+            loop {
+                let snapshot = config.borrow().clone();
+                handle.update(|tray| tray.config_changed(snapshot));
+                if config.changed().await.is_err() {
+                    return;
+                }
+            }
+        });
     }
 }
 